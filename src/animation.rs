@@ -1,7 +1,8 @@
-use std::{collections::HashMap, default, fmt};
+use std::{collections::HashMap, fmt};
 
 use ggez::graphics;
 
+#[derive(Clone)]
 struct Animation
 {
     sheet_pos: (f32,f32),
@@ -55,6 +56,7 @@ impl Animation
         }
     }
 
+    #[allow(dead_code)]
     fn stop(&mut self)
     {
         self.is_playing = false;
@@ -70,6 +72,7 @@ impl fmt::Display for Animation
     }
 }
 
+#[derive(Clone)]
 pub struct Spritesheet
 {
     pub sheet: graphics::Image,
@@ -80,7 +83,9 @@ pub struct Spritesheet
     pub sprite_size: (f32,f32),
 
     step_len: f32,
-    has_animation_in_progress: bool
+    has_animation_in_progress: bool,
+    // fractional ticks carried over when running at a time scale
+    tick_progress: f32
 }
 
 impl Spritesheet
@@ -96,7 +101,8 @@ impl Spritesheet
             animations: HashMap::new(),
             sprite_size,
             step_len,
-            has_animation_in_progress: false
+            has_animation_in_progress: false,
+            tick_progress: 0.0
         }
     }
 
@@ -112,6 +118,13 @@ impl Spritesheet
         self.animations.insert(title.to_string(), Animation::new(sheet_pos, animation_length, loops, frame_delay));
     }
 
+    /// Swap in a new sheet image, animations keep their positions and state
+    pub fn replace_sheet(&mut self, sheet: graphics::Image)
+    {
+        self.img_size = (sheet.width() as f32, sheet.height() as f32);
+        self.sheet = sheet;
+    }
+
     pub fn start_animation(&mut self, title: &str) -> bool
     {
         match self.animations.get_mut(title)
@@ -124,24 +137,30 @@ impl Spritesheet
         }
     }
 
-    pub fn tick(&mut self)
+    /// Advance the animations, scale is how many ticks this counts as
+    pub fn tick(&mut self, scale: f32)
     {
-        for (_, anim) in &mut self.animations
+        self.tick_progress += scale;
+        while self.tick_progress >= 1.0
         {
-            if anim.is_playing
+            self.tick_progress -= 1.0;
+            for anim in self.animations.values_mut()
             {
-                anim.step();
+                if anim.is_playing
+                {
+                    anim.step();
+                }
             }
         }
     }
 
     pub fn draw(&self) -> graphics::Rect
     {
-        for (_, anim) in &self.animations
+        for anim in self.animations.values()
         {
             if anim.is_playing
             {
-                let corrected_size = (self.sprite_size.0 as f32/ self.img_size.0,self.sprite_size.1 as f32/ self.img_size.1 as f32);
+                let corrected_size = (self.sprite_size.0/ self.img_size.0,self.sprite_size.1/ self.img_size.1);
                 let corrected_pos = (
                     (anim.sheet_pos.0 + self.step_len * anim.curr_index as f32) / self.img_size.0,
                     (anim.sheet_pos.1) / self.img_size.1,
@@ -157,7 +176,7 @@ impl Spritesheet
 
         let anim = &self.animations["idle"];
 
-        let corrected_size = (self.sprite_size.0 as f32/ self.img_size.0,self.sprite_size.1 as f32/ self.img_size.1 as f32);
+        let corrected_size = (self.sprite_size.0/ self.img_size.0,self.sprite_size.1/ self.img_size.1);
         let corrected_pos = (
             (anim.sheet_pos.0 + self.step_len * anim.curr_index as f32) / self.img_size.0,
             (anim.sheet_pos.1 + self.step_len * anim.curr_index as f32) / self.img_size.1,
@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use ggez::{filesystem, Context};
+use rand::Rng;
+use specs::*;
+
+use crate::*;
+use crate::course::{Physics, CEILING_Y, FLOOR_Y};
+use crate::obstacles::{ObstaclePair, ObstacleState};
+
+/// The heuristic keeps the bird's bottom this far above the bottom of the gap
+const HEURISTIC_MARGIN:f32 = 25.0;
+/// Frames between the planner's decisions, it can flap at the start of each one
+const PLAN_STEP:usize = 5;
+/// Decisions the planner looks ahead, PLAN_STEP * PLAN_DEPTH frames in total
+const PLAN_DEPTH:usize = 10;
+
+/// Where the recorded policy lives, in the user config dir
+pub const POLICY_FILE:&str = "/policy.txt";
+// size of the buckets the recorded policy sorts what the bird sees into
+const POLICY_DX:f32 = 40.0;
+const POLICY_DY:f32 = 16.0;
+const POLICY_VEL:f32 = 1.0;
+/// A bucket needs to have been seen this often before the recorded policy trusts it
+const POLICY_MIN_SEEN:u32 = 3;
+
+pub const STRATEGIES:[&str; 3] = ["heuristic", "planner", "recorded"];
+
+/// A gap that hasn't been passed yet, as it is right now
+#[derive(Clone, Copy, Debug)]
+pub struct GapView
+{
+    pub x: f32,
+    pub width: f32,
+    pub top: f32,
+    pub bottom: f32
+}
+
+impl GapView
+{
+    pub fn center(&self) -> f32
+    {
+        (self.top + self.bottom) / 2.0
+    }
+}
+
+/// Everything a bot gets to go on for one tick
+#[derive(Clone, Debug)]
+pub struct Observation
+{
+    pub bird: Rect,
+    pub vel_y: f32,
+    /// nearest first
+    pub gaps: Vec<GapView>,
+    pub physics: Physics
+}
+
+impl Observation
+{
+    pub fn new<'a>(bird: &Rect, vel_y: f32, pairs: impl Iterator<Item = (&'a ObstaclePair, &'a ObstacleState)>, physics: Physics) -> Observation
+    {
+        let mut gaps: Vec<GapView> = pairs
+            .filter(|(pair, state)| **state != ObstacleState::Idle && pair.pos_x + pair.width > bird.pos_x)
+            .map(|(pair, _)| GapView {
+                x: pair.pos_x,
+                width: pair.width,
+                top: pair.gap_center - pair.gap_size/2.0,
+                bottom: pair.gap_center + pair.gap_size/2.0
+            })
+            .collect();
+        gaps.sort_by(|a, b| a.x.total_cmp(&b.x));
+        Observation { bird: *bird, vel_y, gaps, physics }
+    }
+
+    pub fn next_gap(&self) -> Option<&GapView>
+    {
+        self.gaps.first()
+    }
+
+    /// Whether the bird's Rect would be clear of everything at height y, frames from now.
+    /// The Rect is a bit bigger than the real collider so this errs on the safe side
+    fn clear(&self, y: f32, frames: usize) -> bool
+    {
+        if y < CEILING_Y || y + self.bird.size_y > FLOOR_Y
+        {
+            return false;
+        }
+        self.gaps.iter().all(|g| {
+            let x = g.x + self.physics.speed * frames as f32;
+            let overlaps = x < self.bird.pos_x + self.bird.size_x && x + g.width > self.bird.pos_x;
+            !overlaps || (y >= g.top && y + self.bird.size_y <= g.bottom)
+        })
+    }
+
+    /// Middle of the first gap the bird hasn't gotten past frames from now, or the middle of the screen
+    fn target(&self, frames: usize) -> f32
+    {
+        self.gaps.iter()
+            .find(|g| g.x + self.physics.speed * frames as f32 + g.width > self.bird.pos_x)
+            .map_or(WINDOW_H/2.0, |g| g.center())
+    }
+}
+
+/// Decides when a bot flaps
+pub trait Strategy: Send + Sync
+{
+    fn name(&self) -> &'static str;
+    fn decide(&mut self, obs: &Observation) -> bool;
+}
+
+/// Flaps whenever the bird's about to sink too low in the next gap
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Heuristic;
+
+impl Strategy for Heuristic
+{
+    fn name(&self) -> &'static str
+    {
+        "heuristic"
+    }
+
+    fn decide(&mut self, obs: &Observation) -> bool
+    {
+        let floor = match obs.next_gap()
+        {
+            Some(gap) => gap.bottom - HEURISTIC_MARGIN,
+            None => WINDOW_H * 0.6
+        };
+        let (next_y, _) = obs.physics.step(obs.bird.pos_y, obs.vel_y, false);
+        next_y + obs.bird.size_y > floor && obs.vel_y >= 0.0
+    }
+}
+
+/// Tries every flap or don't at the start of each PLAN_STEP, takes the first move of
+/// whatever survives longest and ends up closest to the middle of its gap
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Planner;
+
+impl Planner
+{
+    /// (frames survived, distance from the target at the end), only the first frame of the step can flap
+    fn search(obs: &Observation, y: f32, vel_y: f32, frame: usize, depth: usize, flap: bool) -> (usize, f32)
+    {
+        let (mut y, mut vel_y) = (y, vel_y);
+        for i in 0..PLAN_STEP
+        {
+            (y, vel_y) = obs.physics.step(y, vel_y, flap && i == 0);
+            if !obs.clear(y, frame + i + 1)
+            {
+                return (frame + i, f32::INFINITY);
+            }
+        }
+
+        let frame = frame + PLAN_STEP;
+        if depth + 1 >= PLAN_DEPTH
+        {
+            return (frame, (y + obs.bird.size_y/2.0 - obs.target(frame)).abs());
+        }
+        let no_flap = Planner::search(obs, y, vel_y, frame, depth + 1, false);
+        let flap = Planner::search(obs, y, vel_y, frame, depth + 1, true);
+        better(no_flap, flap)
+    }
+}
+
+/// The outcome that survives longer, or gets closer if they both make it as far
+fn better(a: (usize, f32), b: (usize, f32)) -> (usize, f32)
+{
+    if b.0 > a.0 || (b.0 == a.0 && b.1 < a.1) { b } else { a }
+}
+
+impl Strategy for Planner
+{
+    fn name(&self) -> &'static str
+    {
+        "planner"
+    }
+
+    fn decide(&mut self, obs: &Observation) -> bool
+    {
+        let no_flap = Planner::search(obs, obs.bird.pos_y, obs.vel_y, 0, 0, false);
+        let flap = Planner::search(obs, obs.bird.pos_y, obs.vel_y, 0, 0, true);
+        better(no_flap, flap) != no_flap
+    }
+}
+
+/// How often the player flapped in every situation they've been in, recorded while they play
+#[derive(Clone, Debug, Default)]
+pub struct PolicyTable
+{
+    /// bucket to (flaps, times seen)
+    states: HashMap<(i32, i32, i32), (u32, u32)>,
+    file: Option<PathBuf>
+}
+
+impl PolicyTable
+{
+    /// Bucket of where the bird is compared to the next gap, nothing to go on without one
+    fn key(obs: &Observation) -> Option<(i32, i32, i32)>
+    {
+        let gap = obs.next_gap()?;
+        let dx = gap.x - obs.bird.pos_x;
+        let dy = obs.bird.pos_y + obs.bird.size_y/2.0 - gap.center();
+        Some(((dx / POLICY_DX).floor() as i32, (dy / POLICY_DY).floor() as i32, (obs.vel_y / POLICY_VEL).floor() as i32))
+    }
+
+    pub fn record(&mut self, obs: &Observation, flapped: bool)
+    {
+        if let Some(key) = PolicyTable::key(obs)
+        {
+            let (flaps, seen) = self.states.entry(key).or_default();
+            *flaps += flapped as u32;
+            *seen += 1;
+        }
+    }
+
+    /// How likely the player was to flap here, if they've been here enough to tell
+    pub fn flap_chance(&self, obs: &Observation) -> Option<f32>
+    {
+        let (flaps, seen) = self.states.get(&PolicyTable::key(obs)?)?;
+        (*seen >= POLICY_MIN_SEEN).then(|| *flaps as f32 / *seen as f32)
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.states.len()
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.states.clear();
+    }
+
+    /// `dx dy vel flaps seen` per line
+    pub fn parse(contents: &str) -> (PolicyTable, Vec<String>)
+    {
+        let mut table = PolicyTable::default();
+        let mut errors = Vec::new();
+        for (n, line) in contents.lines().enumerate()
+        {
+            let nums: Result<Vec<i64>, _> = line.split_whitespace().map(|w| w.parse::<i64>()).collect();
+            match nums.as_deref()
+            {
+                Ok([dx, dy, vel, flaps, seen]) => {
+                    table.states.insert((*dx as i32, *dy as i32, *vel as i32), (*flaps as u32, *seen as u32));
+                },
+                Ok([]) => {},
+                _ => errors.push(format!("{POLICY_FILE}:{} expected dx dy vel flaps seen", n + 1))
+            }
+        }
+        (table, errors)
+    }
+
+    pub fn save(&self) -> Result<String, String>
+    {
+        let file = self.file.as_ref().ok_or("nowhere to save the policy to")?;
+        let mut lines: Vec<String> = self.states.iter()
+            .map(|((dx, dy, vel), (flaps, seen))| format!("{dx} {dy} {vel} {flaps} {seen}"))
+            .collect();
+        lines.sort();
+        std::fs::write(file, lines.join("\n") + "\n").map_err(|e| format!("couldn't save {}: {e}", file.display()))?;
+        Ok(format!("saved {} states to {}", self.len(), file.display()))
+    }
+}
+
+/// Plays like the player did, falls back to the heuristic wherever the player hasn't been enough
+#[derive(Clone, Debug)]
+pub struct Recorded
+{
+    pub table: PolicyTable
+}
+
+impl Strategy for Recorded
+{
+    fn name(&self) -> &'static str
+    {
+        "recorded"
+    }
+
+    fn decide(&mut self, obs: &Observation) -> bool
+    {
+        match self.table.flap_chance(obs)
+        {
+            Some(chance) => rand::thread_rng().gen::<f32>() < chance,
+            None => Heuristic.decide(obs)
+        }
+    }
+}
+
+/// A strategy by name, the recorded one gets a copy of the policy as it is now
+pub fn strategy(name: &str, table: &PolicyTable) -> Option<Box<dyn Strategy>>
+{
+    match name
+    {
+        "heuristic" => Some(Box::new(Heuristic)),
+        "planner" => Some(Box::new(Planner)),
+        "recorded" => Some(Box::new(Recorded { table: table.clone() })),
+        _ => None
+    }
+}
+
+/// Who's flapping a Controllable
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub enum Pilot
+{
+    /// the mouse, see PlayerInput
+    Player,
+    Bot(Box<dyn Strategy>)
+}
+
+impl Pilot
+{
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            Pilot::Player => "player",
+            Pilot::Bot(strategy) => strategy.name()
+        }
+    }
+}
+
+/// Flaps asked for by the player since the last tick
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PlayerInput
+{
+    pub flap: bool
+}
+
+/// Load the recorded policy from the user config dir, there's just nothing recorded if it isn't there
+pub fn load_policy(ctx: &mut Context, world: &mut World) -> Vec<String>
+{
+    let file = filesystem::user_config_dir(ctx).join(POLICY_FILE.trim_start_matches('/'));
+    let (mut table, errors) = match std::fs::read_to_string(&file)
+    {
+        Ok(contents) => PolicyTable::parse(&contents),
+        Err(_) => (PolicyTable::default(), Vec::new())
+    };
+    table.file = Some(file);
+    world.insert(table);
+    errors
+}
+
+/// Hand every Controllable to a strategy by name, or back to the player with off
+pub fn set_pilot(world: &mut World, name: &str) -> Result<String, String>
+{
+    if name != "off" && !STRATEGIES.contains(&name)
+    {
+        return Err(format!("unknown strategy '{name}', try off, {}", STRATEGIES.join(", ")));
+    }
+
+    let entities = world.entities();
+    let control = world.read_storage::<Controllable>();
+    let table = world.read_resource::<PolicyTable>();
+    let mut pilots = world.write_storage::<Pilot>();
+    for (ent, _) in (&entities, &control).join()
+    {
+        // every bird gets its own, strategies can keep state between ticks
+        let pilot = match strategy(name, &table)
+        {
+            Some(strategy) => Pilot::Bot(strategy),
+            None => Pilot::Player
+        };
+        if let Err(e) = pilots.insert(ent, pilot)
+        {
+            return Err(format!("couldn't set the pilot of {}: {e}", ent.id()));
+        }
+    }
+    Ok(if name == "off" { "autopilot off".to_string() } else { format!("{name} flying") })
+}
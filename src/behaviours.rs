@@ -0,0 +1,239 @@
+use std::f32::consts::TAU;
+use std::fmt;
+
+use specs::*;
+
+use crate::*;
+use crate::course::Gap;
+use crate::obstacles::ObstaclePair;
+
+/// Frames before a dropping sausage reaches the bird that it starts falling
+const DROP_LEAD_FRAMES:f32 = 90.0;
+/// Pixels per frame the falling sausage speeds up by
+const DROP_ACCEL:f32 = 0.5;
+
+/// How the pairs of a pattern move once they're sent in
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Motion
+{
+    #[default]
+    Still,
+    /// gap moves up and down by amplitude pixels, once every period seconds
+    Oscillate { amplitude: f32, period: f32 },
+    /// gap closes down to min_size and opens back up, once every period seconds
+    Breathe { min_size: f32, period: f32 },
+    /// the top sausage sits in the ceiling and drops in when the pair gets close
+    Drop
+}
+
+impl Motion
+{
+    /// Parse `still`, `oscillate <amplitude> <period>`, `breathe <min gap> <period>` or `drop`
+    pub fn parse(value: &str) -> Result<Motion, String>
+    {
+        let mut words = value.split_whitespace();
+        let kind = words.next().unwrap_or("");
+        let mut arg = |name: &str| -> Result<f32, String> {
+            let word = words.next().ok_or(format!("{kind} needs a {name}"))?;
+            word.parse::<f32>().map_err(|e| format!("'{word}' isn't a valid {name} ({e})"))
+        };
+
+        match kind
+        {
+            "still" => Ok(Motion::Still),
+            "oscillate" => Ok(Motion::Oscillate { amplitude: arg("amplitude")?.abs(), period: arg("period")?.max(0.1) }),
+            "breathe" => Ok(Motion::Breathe { min_size: arg("gap")?.max(0.0), period: arg("period")?.max(0.1) }),
+            "drop" => Ok(Motion::Drop),
+            _ => Err(format!("unknown motion '{kind}'"))
+        }
+    }
+
+    /// How far the gap center strays from where it was rolled
+    pub fn reach(&self) -> f32
+    {
+        match self
+        {
+            Motion::Oscillate { amplitude, .. } => *amplitude,
+            _ => 0.0
+        }
+    }
+
+    /// Size of the part of the gap that's open the whole time
+    pub fn tightest(&self, gap_size: f32) -> f32
+    {
+        match self
+        {
+            Motion::Oscillate { amplitude, .. } => (gap_size - 2.0 * amplitude).max(0.0),
+            Motion::Breathe { min_size, .. } => min_size.min(gap_size),
+            Motion::Still | Motion::Drop => gap_size
+        }
+    }
+}
+
+/// Same format parse() takes
+impl fmt::Display for Motion
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            Motion::Still => write!(f, "still"),
+            Motion::Oscillate { amplitude, period } => write!(f, "oscillate {amplitude} {period}"),
+            Motion::Breathe { min_size, period } => write!(f, "breathe {min_size} {period}"),
+            Motion::Drop => write!(f, "drop")
+        }
+    }
+}
+
+/// Gap center swings around base
+#[derive(Component, Debug)]
+#[storage(DenseVecStorage)]
+pub struct Oscillate
+{
+    pub base: f32,
+    pub amplitude: f32,
+    pub period: f32,
+    pub time: f32
+}
+
+impl Oscillate
+{
+    pub fn update(&mut self, pair: &mut ObstaclePair, dt: f32)
+    {
+        self.time += dt;
+        pair.gap_center = self.base + self.amplitude * (TAU * self.time / self.period).sin();
+    }
+}
+
+/// Gap size goes from base down to min_size and back, starting fully open
+#[derive(Component, Debug)]
+#[storage(DenseVecStorage)]
+pub struct Breathe
+{
+    pub base: f32,
+    pub min_size: f32,
+    pub period: f32,
+    pub time: f32
+}
+
+impl Breathe
+{
+    pub fn update(&mut self, pair: &mut ObstaclePair, dt: f32)
+    {
+        self.time += dt;
+        let closed = (1.0 - (TAU * self.time / self.period).cos()) / 2.0;
+        pair.gap_size = self.base - (self.base - self.min_size) * closed;
+    }
+}
+
+/// Top of the gap falls from the ceiling down to target_top, the bottom stays put
+#[derive(Component, Debug)]
+#[storage(DenseVecStorage)]
+pub struct DropIn
+{
+    pub top: f32,
+    pub target_top: f32,
+    pub bottom: f32,
+    pub vel: f32
+}
+
+impl DropIn
+{
+    pub fn update(&mut self, pair: &mut ObstaclePair, speed: f32, scale: f32)
+    {
+        // frames until the pair reaches the bird at its current speed
+        let frames_left = (pair.pos_x - BIRD_X - SQUARE_SIZE) / speed.abs().max(0.01);
+        if self.top < self.target_top && frames_left < DROP_LEAD_FRAMES
+        {
+            self.vel += DROP_ACCEL * scale;
+            self.top = (self.top + self.vel * scale).min(self.target_top);
+        }
+
+        pair.gap_center = (self.top + self.bottom) / 2.0;
+        pair.gap_size = self.bottom - self.top;
+    }
+}
+
+/// Every motion component, a pair has at most one of them
+pub type Motions<'a> = (WriteStorage<'a, Oscillate>, WriteStorage<'a, Breathe>, WriteStorage<'a, DropIn>);
+
+/// Take whatever motion a pair had off it
+pub fn detach(motions: &mut Motions, entity: Entity)
+{
+    motions.0.remove(entity);
+    motions.1.remove(entity);
+    motions.2.remove(entity);
+}
+
+/// Give a freshly sent in pair the components for its motion. Done straight away and not through
+/// LazyUpdate, otherwise the motion system gets a tick with the last pair's motion on it
+pub fn attach(motions: &mut Motions, entity: Entity, pair: &mut ObstaclePair, gap: &Gap)
+{
+    detach(motions, entity);
+
+    // inserting only fails for dead entities and the pair came out of a join
+    match gap.motion
+    {
+        Motion::Still => {},
+        Motion::Oscillate { amplitude, period } => {
+            let _ = motions.0.insert(entity, Oscillate {
+                base: gap.center,
+                amplitude,
+                period,
+                time: 0.0
+            });
+        },
+        Motion::Breathe { min_size, period } => {
+            let _ = motions.1.insert(entity, Breathe {
+                base: gap.size,
+                min_size: min_size.min(gap.size),
+                period,
+                time: 0.0
+            });
+        },
+        Motion::Drop => {
+            let drop = DropIn {
+                top: 0.0,
+                target_top: gap.center - gap.size/2.0,
+                bottom: gap.center + gap.size/2.0,
+                vel: 0.0
+            };
+            // start with the top sausage all the way up
+            pair.gap_center = drop.bottom / 2.0;
+            pair.gap_size = drop.bottom;
+            let _ = motions.2.insert(entity, drop);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn motion_round_trip()
+    {
+        let motions = [
+            Motion::Still,
+            Motion::Oscillate { amplitude: 42.5, period: 1.75 },
+            Motion::Breathe { min_size: 130.0, period: 0.3 },
+            Motion::Drop
+        ];
+        for motion in motions
+        {
+            assert_eq!(Motion::parse(&motion.to_string()), Ok(motion));
+        }
+    }
+
+    #[test]
+    fn motion_parse()
+    {
+        assert_eq!(Motion::parse("  oscillate   -20 0 "), Ok(Motion::Oscillate { amplitude: 20.0, period: 0.1 }));
+        assert_eq!(Motion::parse("breathe -5 2"), Ok(Motion::Breathe { min_size: 0.0, period: 2.0 }));
+        assert!(Motion::parse("oscillate 20").is_err());
+        assert!(Motion::parse("breathe x 2").is_err());
+        assert!(Motion::parse("spin").is_err());
+        assert!(Motion::parse("").is_err());
+    }
+}
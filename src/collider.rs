@@ -0,0 +1,551 @@
+use ggez::graphics::{self, Color, DrawMode, MeshBuilder};
+use ggez::GameResult;
+use specs::*;
+
+use crate::*;
+
+/// Alpha at or above this counts as solid
+const ALPHA_SOLID:u8 = 128;
+/// World pixels between alpha mask samples
+const MASK_STEP:f32 = 1.5;
+/// How far two things can move relative to each other between swept collision checks
+const SWEEP_STEP:f32 = 4.0;
+/// Past this many the steps just get longer, a huge move shouldn't cost hundreds of tests
+const MAX_SWEEP_STEPS:usize = 32;
+
+/// Collision shape, centered on the collider's offset from its pose
+#[derive(Clone, Copy, Debug)]
+pub enum Shape
+{
+    Circle { radius: f32 },
+    /// rounded line along the collider's x axis
+    Capsule { half_length: f32, radius: f32 },
+    /// oriented box
+    Obb { half_w: f32, half_h: f32 }
+}
+
+/// Where the sprite a collider belongs to is this tick, the point it's drawn and turned around
+#[derive(Clone, Copy, Debug)]
+pub struct Pose
+{
+    pub anchor: Vec2,
+    pub angle: f32
+}
+
+/// Solid pixels of a sprite sheet, tested after the shape so only drawn pixels kill
+#[derive(Clone, Debug)]
+pub struct AlphaMask
+{
+    width: usize,
+    height: usize,
+    solid: Vec<bool>
+}
+
+impl AlphaMask
+{
+    pub fn from_rgba(width: usize, height: usize, rgba: &[u8]) -> AlphaMask
+    {
+        AlphaMask {
+            width,
+            height,
+            solid: rgba.chunks_exact(4).map(|px| px[3] >= ALPHA_SOLID).collect()
+        }
+    }
+
+    pub fn from_image(ctx: &mut ggez::Context, img: &graphics::Image) -> GameResult<AlphaMask>
+    {
+        let rgba = img.to_rgba8(ctx)?;
+        Ok(AlphaMask::from_rgba(img.width() as usize, img.height() as usize, &rgba))
+    }
+
+    fn is_solid(&self, x: f32, y: f32) -> bool
+    {
+        if x < 0.0 || y < 0.0
+        {
+            return false;
+        }
+        let (x, y) = (x as usize, y as usize);
+        x < self.width && y < self.height && self.solid[y * self.width + x]
+    }
+}
+
+/// The sprite frame a mask test looks at, src is normalized like DrawParam::src
+#[derive(Clone, Copy, Debug)]
+pub struct SpriteFrame
+{
+    pub src: graphics::Rect,
+    pub scale: f32
+}
+
+/// Shape hitbox that follows the sprite instead of the Rect.
+/// The Rect is still the entity's position, the sprite and collider hang off it
+#[derive(Component, Clone, Debug)]
+#[storage(DenseVecStorage)]
+pub struct Collider
+{
+    pub shape: Shape,
+    /// from the Rect's corner to where the sprite is drawn and turned around
+    pub anchor: Vec2,
+    /// from the anchor to the shape's center, turns with the sprite
+    pub offset: Vec2,
+    /// turn with the sprite the way draw() does, from the vertical velocity
+    pub follow_rotation: bool,
+    pub mask: Option<AlphaMask>,
+    /// the mask is kept around while this is off so it can be flipped back on
+    pub use_mask: bool
+}
+
+impl Shape
+{
+    /// `circle <radius>`, `capsule <half length> <radius>` or `box <half width> <half height>`
+    pub fn parse(args: &[&str]) -> Result<Shape, String>
+    {
+        let num = |i: usize, name: &str| -> Result<f32, String> {
+            let arg = args.get(i).ok_or(format!("missing {name}"))?;
+            arg.parse::<f32>().map(f32::abs).map_err(|e| format!("'{arg}' isn't a valid {name} ({e})"))
+        };
+        match args.first()
+        {
+            Some(&"circle") => Ok(Shape::Circle { radius: num(1, "radius")? }),
+            Some(&"capsule") => Ok(Shape::Capsule { half_length: num(1, "half length")?, radius: num(2, "radius")? }),
+            Some(&"box") => Ok(Shape::Obb { half_w: num(1, "half width")?, half_h: num(2, "half height")? }),
+            _ => Err("shape should be circle, capsule or box".to_string())
+        }
+    }
+}
+
+impl Collider
+{
+    /// The bird's body, sized off the idle frame of bird.png and kept inside the
+    /// Rect's width so the course validator's square bird is still fair
+    pub fn bird(sprite_size: (f32, f32), scale: f32) -> Collider
+    {
+        Collider {
+            shape: Shape::Capsule { half_length: 12.0, radius: 20.0 },
+            // same spot draw() puts the sprite's center
+            anchor: Vec2::new(sprite_size.0, sprite_size.1 * 1.5),
+            // the body sits a couple of sprite pixels above the frame's center
+            offset: Vec2::new(0.0, -2.0 * scale),
+            follow_rotation: true,
+            mask: None,
+            use_mask: true
+        }
+    }
+
+    pub fn pose(&self, r: &Rect, vel_y: f32) -> Pose
+    {
+        let angle = if self.follow_rotation { translate_player_rotation(&vel_y) } else { 0.0 };
+        Pose {
+            anchor: Vec2::new(r.pos_x, r.pos_y) + self.anchor,
+            angle
+        }
+    }
+
+    pub fn center(&self, pose: Pose) -> Vec2
+    {
+        pose.anchor + rotate(self.offset, pose.angle)
+    }
+
+    /// A Rect the shape stays inside however it's turned, for skipping what's nowhere near it
+    pub fn bounds(&self, r: &Rect) -> Rect
+    {
+        let reach = self.offset.length() + match self.shape
+        {
+            Shape::Circle { radius } => radius,
+            Shape::Capsule { half_length, radius } => half_length + radius,
+            Shape::Obb { half_w, half_h } => Vec2::new(half_w, half_h).length()
+        };
+        Rect {
+            pos_x: r.pos_x + self.anchor.x - reach,
+            pos_y: r.pos_y + self.anchor.y - reach,
+            size_x: 2.0 * reach,
+            size_y: 2.0 * reach
+        }
+    }
+
+    /// Does the shape overlap an axis aligned Rect
+    pub fn hits(&self, pose: Pose, r: &Rect) -> bool
+    {
+        let (min, max) = (Vec2::new(r.pos_x, r.pos_y), Vec2::new(r.pos_x + r.size_x, r.pos_y + r.size_y));
+        let center = self.center(pose);
+        match self.shape
+        {
+            Shape::Circle { radius } => {
+                let closest = center.clamp(min, max);
+                closest.distance_squared(center) <= radius * radius
+            },
+            Shape::Capsule { half_length, radius } => {
+                let along = rotate(Vec2::new(half_length, 0.0), pose.angle);
+                segment_box_distance(center - along, center + along, min, max) <= radius
+            },
+            Shape::Obb { half_w, half_h } => {
+                // separating axis test, the box's two axes and the Rect's two
+                let corners = obb_corners(center, pose.angle, half_w, half_h);
+                let rect_corners = [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)];
+                let axes = [Vec2::X, Vec2::Y, rotate(Vec2::X, pose.angle), rotate(Vec2::Y, pose.angle)];
+                axes.iter().all(|axis| {
+                    let (a_min, a_max) = project(&corners, *axis);
+                    let (b_min, b_max) = project(&rect_corners, *axis);
+                    a_min <= b_max && b_min <= a_max
+                })
+            }
+        }
+    }
+
+    /// Is the point inside the shape
+    pub fn contains(&self, pose: Pose, point: Vec2) -> bool
+    {
+        let center = self.center(pose);
+        match self.shape
+        {
+            Shape::Circle { radius } => point.distance_squared(center) <= radius * radius,
+            Shape::Capsule { half_length, radius } => {
+                let along = rotate(Vec2::new(half_length, 0.0), pose.angle);
+                point_segment_distance(point, center - along, center + along) <= radius
+            },
+            Shape::Obb { half_w, half_h } => {
+                let local = rotate(point - center, -pose.angle);
+                local.x.abs() <= half_w && local.y.abs() <= half_h
+            }
+        }
+    }
+
+    /// Is any solid sprite pixel inside both the Rect and the shape, true when there's no mask to check
+    pub fn mask_hits(&self, pose: Pose, frame: SpriteFrame, r: &Rect) -> bool
+    {
+        let Some(mask) = self.mask.as_ref().filter(|_| self.use_mask) else {
+            return true;
+        };
+
+        let frame_size = Vec2::new(frame.src.w * mask.width as f32, frame.src.h * mask.height as f32);
+        let frame_pos = Vec2::new(frame.src.x * mask.width as f32, frame.src.y * mask.height as f32);
+        let sprite_center = pose.anchor;
+
+        // only sample where the Rect and the sprite's bounds overlap
+        let reach = frame_size.length() * frame.scale / 2.0;
+        let x_range = (r.pos_x.max(sprite_center.x - reach), (r.pos_x + r.size_x).min(sprite_center.x + reach));
+        let y_range = (r.pos_y.max(sprite_center.y - reach), (r.pos_y + r.size_y).min(sprite_center.y + reach));
+
+        let mut y = y_range.0;
+        while y <= y_range.1
+        {
+            let mut x = x_range.0;
+            while x <= x_range.1
+            {
+                let local = rotate(Vec2::new(x, y) - sprite_center, -pose.angle) / frame.scale + frame_size / 2.0;
+                if local.x >= 0.0 && local.y >= 0.0 && local.x < frame_size.x && local.y < frame_size.y
+                    && mask.is_solid(frame_pos.x + local.x, frame_pos.y + local.y)
+                    && self.contains(pose, Vec2::new(x, y))
+                {
+                    return true;
+                }
+                x += MASK_STEP;
+            }
+            y += MASK_STEP;
+        }
+        false
+    }
+
+    /// Outline for the hitbox debug view
+    pub fn outline(&self, mb: &mut MeshBuilder, pose: Pose, color: Color) -> GameResult
+    {
+        let center = self.center(pose);
+        match self.shape
+        {
+            Shape::Circle { radius } => {
+                mb.circle(DrawMode::stroke(2.0), center, radius, 0.5, color)?;
+            },
+            Shape::Capsule { half_length, radius } => {
+                let along = rotate(Vec2::new(half_length, 0.0), pose.angle);
+                let side = rotate(Vec2::new(0.0, radius), pose.angle);
+                mb.circle(DrawMode::stroke(2.0), center - along, radius, 0.5, color)?;
+                mb.circle(DrawMode::stroke(2.0), center + along, radius, 0.5, color)?;
+                mb.line(&[center - along - side, center + along - side], 2.0, color)?;
+                mb.line(&[center - along + side, center + along + side], 2.0, color)?;
+            },
+            Shape::Obb { half_w, half_h } => {
+                mb.polygon(DrawMode::stroke(2.0), &obb_corners(center, pose.angle, half_w, half_h), color)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn rotate(v: Vec2, angle: f32) -> Vec2
+{
+    let (sin, cos) = angle.sin_cos();
+    Vec2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos)
+}
+
+fn obb_corners(center: Vec2, angle: f32, half_w: f32, half_h: f32) -> [Vec2; 4]
+{
+    [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+        .map(|(sx, sy)| center + rotate(Vec2::new(sx * half_w, sy * half_h), angle))
+}
+
+fn project(points: &[Vec2], axis: Vec2) -> (f32, f32)
+{
+    points.iter().map(|p| p.dot(axis))
+        .fold((f32::MAX, f32::MIN), |(lo, hi), d| (lo.min(d), hi.max(d)))
+}
+
+fn point_segment_distance(p: Vec2, a: Vec2, b: Vec2) -> f32
+{
+    let ab = b - a;
+    let t = if ab.length_squared() > 0.0 { ((p - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0) } else { 0.0 };
+    p.distance(a + ab * t)
+}
+
+/// Shortest distance between a line segment and a box, 0 if they cross
+fn segment_box_distance(a: Vec2, b: Vec2, min: Vec2, max: Vec2) -> f32
+{
+    // clip the segment against the box slabs, anything left over is inside
+    let dir = b - a;
+    let (mut t0, mut t1) = (0.0_f32, 1.0_f32);
+    let mut crosses = true;
+    for (start, d, lo, hi) in [(a.x, dir.x, min.x, max.x), (a.y, dir.y, min.y, max.y)]
+    {
+        if d.abs() < f32::EPSILON
+        {
+            crosses &= start >= lo && start <= hi;
+            continue;
+        }
+        let (ta, tb) = ((lo - start) / d, (hi - start) / d);
+        t0 = t0.max(ta.min(tb));
+        t1 = t1.min(ta.max(tb));
+    }
+    if crosses && t0 <= t1
+    {
+        return 0.0;
+    }
+
+    // otherwise the closest points are an end of the segment or a corner of the box
+    let corners = [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)];
+    let ends = [a, b].map(|p| p.distance(p.clamp(min, max)));
+    corners.iter().map(|c| point_segment_distance(*c, a, b))
+        .chain(ends)
+        .fold(f32::MAX, f32::min)
+}
+
+/// Sweeps both rects from where they were at the start of the tick to where they are now,
+/// so fast movers can't skip through each other between two ticks.
+/// Returns the time of impact, 0 is the start of the tick and 1 the end
+pub fn time_of_impact(from: (&Rect, &Rect), to: (&Rect, &Rect), mut hits: impl FnMut(&Rect, &Rect) -> bool) -> Option<f32>
+{
+    // only the movement relative to each other matters for how fine the steps have to be
+    let moved = |from: &Rect, to: &Rect| Vec2::new(to.pos_x - from.pos_x, to.pos_y - from.pos_y);
+    let relative = moved(from.0, to.0) - moved(from.1, to.1);
+    // teleports reset LastRect so they never get here, anything this far is just really fast
+    let steps = ((relative.length() / SWEEP_STEP).ceil() as usize).clamp(1, MAX_SWEEP_STEPS);
+
+    (1..=steps)
+        .map(|i| i as f32 / steps as f32)
+        .find(|t| hits(&from.0.lerp(to.0, *t), &from.1.lerp(to.1, *t)))
+}
+
+/// What the bird ran into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollisionKind
+{
+    Ceiling,
+    Floor,
+    ObstacleTop,
+    ObstacleBottom,
+    Other
+}
+
+/// Published on the EventChannel<CollisionEvent> resource for every hit,
+/// anything that cares (game over, sound, stats) keeps its own reader
+#[derive(Clone, Copy, Debug)]
+pub struct CollisionEvent
+{
+    pub bird: Entity,
+    pub other: Entity,
+    pub kind: CollisionKind,
+    /// on the surface of what got hit, closest to the bird's center
+    pub point: Vec2,
+    /// points out of what got hit towards the bird
+    pub normal: Vec2,
+    /// 0 is the start of the tick and 1 the end, see time_of_impact
+    pub toi: f32
+}
+
+/// Contact point and normal between a point (the bird's center) and a Rect
+pub fn contact(center: Vec2, r: &Rect) -> (Vec2, Vec2)
+{
+    let (min, max) = (Vec2::new(r.pos_x, r.pos_y), Vec2::new(r.pos_x + r.size_x, r.pos_y + r.size_y));
+    let closest = center.clamp(min, max);
+    if closest != center
+    {
+        return (closest, (center - closest).normalize_or_zero());
+    }
+
+    // center's inside, push out through whichever side is nearest
+    [
+        (center.x - min.x, Vec2::new(min.x, center.y), -Vec2::X),
+        (max.x - center.x, Vec2::new(max.x, center.y), Vec2::X),
+        (center.y - min.y, Vec2::new(center.x, min.y), -Vec2::Y),
+        (max.y - center.y, Vec2::new(center.x, max.y), Vec2::Y)
+    ]
+        .into_iter()
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, point, normal)| (point, normal))
+        .unwrap_or((center, Vec2::ZERO))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn rect(pos_x: f32, pos_y: f32, size_x: f32, size_y: f32) -> Rect
+    {
+        Rect { pos_x, pos_y, size_x, size_y }
+    }
+
+    fn collider(shape: Shape) -> Collider
+    {
+        Collider {
+            shape,
+            anchor: Vec2::ZERO,
+            offset: Vec2::ZERO,
+            follow_rotation: false,
+            mask: None,
+            use_mask: true
+        }
+    }
+
+    fn pose(x: f32, y: f32, angle: f32) -> Pose
+    {
+        Pose { anchor: Vec2::new(x, y), angle }
+    }
+
+    #[test]
+    fn obb_separating_axis()
+    {
+        let obb = collider(Shape::Obb { half_w: 10.0, half_h: 10.0 });
+        let diamond = pose(0.0, 0.0, std::f32::consts::FRAC_PI_4);
+
+        // straight boxes just overlap like rects
+        assert!(obb.hits(pose(0.0, 0.0, 0.0), &rect(9.0, 9.0, 10.0, 10.0)));
+        assert!(!obb.hits(pose(0.0, 0.0, 0.0), &rect(11.0, -5.0, 10.0, 10.0)));
+        // turned 45 degrees its corners stick out to 14.1 along the axes, but the diagonal edge
+        // passes by the rect's corner, only the box's own axes separate them
+        assert!(obb.hits(diamond, &rect(13.0, -2.0, 10.0, 4.0)));
+        assert!(obb.hits(diamond, &rect(5.0, 5.0, 10.0, 10.0)));
+        assert!(!obb.hits(diamond, &rect(9.0, 9.0, 10.0, 10.0)));
+        assert!(!obb.hits(diamond, &rect(-19.0, 9.0, 10.0, 10.0)));
+    }
+
+    #[test]
+    fn capsule_turns()
+    {
+        let capsule = collider(Shape::Capsule { half_length: 20.0, radius: 5.0 });
+        let above = rect(-2.0, -27.0, 4.0, 4.0);
+        assert!(!capsule.hits(pose(0.0, 0.0, 0.0), &above));
+        assert!(capsule.hits(pose(0.0, 0.0, std::f32::consts::FRAC_PI_2), &above));
+    }
+
+    #[test]
+    fn segment_box()
+    {
+        let (min, max) = (Vec2::new(0.0, 0.0), Vec2::new(10.0, 10.0));
+        let distance = |a: (f32, f32), b: (f32, f32)| segment_box_distance(a.into(), b.into(), min, max);
+
+        // crossing right through, only the middle inside, and all inside
+        assert_eq!(distance((-5.0, 5.0), (15.0, 5.0)), 0.0);
+        assert_eq!(distance((-5.0, -5.0), (15.0, 15.0)), 0.0);
+        assert_eq!(distance((2.0, 2.0), (8.0, 3.0)), 0.0);
+        // running alongside an edge
+        assert!((distance((-5.0, 13.0), (15.0, 13.0)) - 3.0).abs() < 1e-5);
+        // an end closest to a face, then to a corner
+        assert!((distance((14.0, 5.0), (20.0, 5.0)) - 4.0).abs() < 1e-5);
+        assert!((distance((13.0, 14.0), (20.0, 20.0)) - 5.0).abs() < 1e-5);
+        // the box corner closest to the middle of the segment
+        assert!((distance((16.0, 0.0), (6.0, -10.0)).powi(2) - 18.0).abs() < 1e-4);
+        // a segment that's a point
+        assert!((distance((-3.0, 5.0), (-3.0, 5.0)) - 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn contact_normals()
+    {
+        let r = rect(0.0, 0.0, 10.0, 20.0);
+
+        assert_eq!(contact(Vec2::new(-4.0, 5.0), &r), (Vec2::new(0.0, 5.0), -Vec2::X));
+        assert_eq!(contact(Vec2::new(5.0, 30.0), &r), (Vec2::new(5.0, 20.0), Vec2::Y));
+        let (point, normal) = contact(Vec2::new(13.0, -3.0), &r);
+        assert_eq!(point, Vec2::new(10.0, 0.0));
+        assert!((normal - Vec2::new(1.0, -1.0).normalize()).length() < 1e-5);
+
+        // inside it pushes out the nearest side
+        assert_eq!(contact(Vec2::new(8.0, 10.0), &r), (Vec2::new(10.0, 10.0), Vec2::X));
+        assert_eq!(contact(Vec2::new(5.0, 1.0), &r), (Vec2::new(5.0, 0.0), -Vec2::Y));
+    }
+
+    #[test]
+    fn mask_only_counts_pixels_in_the_shape()
+    {
+        let mut circle = collider(Shape::Circle { radius: 8.0 });
+        circle.mask = Some(AlphaMask::from_rgba(64, 64, &[255; 64 * 64 * 4]));
+        let frame = SpriteFrame { src: graphics::Rect::new(0.0, 0.0, 1.0, 1.0), scale: 1.0 };
+        let at = pose(100.0, 100.0, 0.0);
+
+        // solid sprite there, but outside the circle
+        let corner = rect(120.0, 120.0, 5.0, 5.0);
+        assert!(!circle.mask_hits(at, frame, &corner));
+        assert!(circle.mask_hits(at, frame, &rect(104.0, 98.0, 10.0, 4.0)));
+
+        // nothing solid under the circle
+        let mut hollow = [255; 64 * 64 * 4];
+        for px in hollow.chunks_exact_mut(4 * 64).skip(16).take(32).flat_map(|row| row.chunks_exact_mut(4).skip(16).take(32))
+        {
+            px[3] = 0;
+        }
+        circle.mask = Some(AlphaMask::from_rgba(64, 64, &hollow));
+        assert!(!circle.mask_hits(at, frame, &rect(90.0, 90.0, 20.0, 20.0)));
+
+        circle.use_mask = false;
+        assert!(circle.mask_hits(at, frame, &corner));
+    }
+
+    #[test]
+    fn fast_crossing_has_a_time_of_impact()
+    {
+        let overlaps = |a: &Rect, b: &Rect| a.overlaps(b);
+        // a bird moving 300 pixels in a tick, straight through a 4 pixel wide pipe
+        let (from, to) = (rect(0.0, 100.0, 20.0, 20.0), rect(300.0, 100.0, 20.0, 20.0));
+        let pipe = rect(150.0, 0.0, 4.0, 300.0);
+        let toi = time_of_impact((&from, &pipe), (&to, &pipe), overlaps).expect("the sweep finds the pipe");
+        assert!((130.0/300.0..=154.0/300.0).contains(&toi), "{toi}");
+        // only checking where it ended up misses it
+        assert!(!overlaps(&to, &pipe));
+
+        // the pipe moving just as fast the other way
+        let (pipe_from, pipe_to) = (rect(300.0, 0.0, 4.0, 300.0), rect(0.0, 0.0, 4.0, 300.0));
+        let still = rect(140.0, 100.0, 20.0, 20.0);
+        assert!(time_of_impact((&still, &pipe_from), (&still, &pipe_to), overlaps).is_some());
+
+        // passing by above it
+        let (over_from, over_to) = (rect(0.0, -40.0, 20.0, 20.0), rect(300.0, -40.0, 20.0, 20.0));
+        assert_eq!(time_of_impact((&over_from, &pipe), (&over_to, &pipe), overlaps), None);
+    }
+
+    #[test]
+    fn teleport_has_no_time_of_impact()
+    {
+        let overlaps = |a: &Rect, b: &Rect| a.overlaps(b);
+        let pipe = rect(150.0, 0.0, 4.0, 300.0);
+        // a reset puts LastRect where the bird is now, so there's nothing in between to sweep
+        let reset = rect(300.0, 100.0, 20.0, 20.0);
+        assert_eq!(time_of_impact((&reset, &pipe), (&reset, &pipe), overlaps), None);
+
+        // same for a pair that got recycled to the other side of the bird
+        let bird = rect(100.0, 100.0, 20.0, 20.0);
+        let placed = rect(900.0, 0.0, 4.0, 300.0);
+        assert_eq!(time_of_impact((&bird, &placed), (&bird, &placed), overlaps), None);
+
+        // but something reset on top of the bird still hits at the end of the tick
+        assert_eq!(time_of_impact((&bird, &bird), (&bird, &bird), overlaps), Some(1.0));
+    }
+}
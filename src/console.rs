@@ -0,0 +1,557 @@
+use specs::*;
+
+use crate::*;
+use crate::debug::{DebugSettings, TimeScale};
+use crate::obstacles::*;
+
+/// Scrollback, older lines fall off the top
+const MAX_LOG_LINES:usize = 1000;
+/// How many log lines the console shows at once, the whole help has to fit
+pub const VISIBLE_LINES:usize = 24;
+
+const HELP:&str = "\
+help                         this text
+get <var>                    gravity, flap, speed, timescale, master, music, sfx, mute,
+                             score, difficulty, multiplier, combo, scoring, state, impact,
+                             pattern (get only)
+set <var> <value>            same vars as get
+spawn                        send in the next idle obstacle pair
+clear                        put every obstacle back in the pool with a new gap
+debug <toggle> [on|off]      hitboxes, movement, collision, obstacles, god
+dump [component]             entities, or one of rect, velocity, obstacle
+collider [shape|mask on|off] show the bird's collider, or swap its shape for circle, capsule or box
+levels                       list the levels, * is the one the menu starts
+level <name>                 pick a level, or endless
+validate [courses] [length]  prove generated courses are passable at the current tuning and difficulty
+autopilot [off|strategy]     who's flying the bird, or hand it to heuristic, planner or recorded
+policy [save|clear]          what the recorded strategy has learned from the player
+evolve [size|off]            train a population of birds with neural nets, one generation per run
+freeze                       freeze or unfreeze the simulation
+step                         advance one tick while frozen
+cls                          clear this log, PageUp/PageDown scroll it";
+
+/// Drop-down developer console, opened with backtick
+/// Commands poke directly at the World resources so playtesting doesn't need a recompile
+#[derive(Default)]
+pub struct Console
+{
+    pub open: bool,
+    pub input: String,
+    log: Vec<String>,
+    /// lines scrolled up from the bottom of the log
+    scroll: usize,
+    history: Vec<String>,
+    history_index: Option<usize>,
+}
+
+impl Console
+{
+    pub fn toggle(&mut self)
+    {
+        self.open = !self.open;
+    }
+
+    /// The part of the log that's on screen
+    pub fn visible(&self) -> &[String]
+    {
+        let end = self.log.len() - self.scroll;
+        &self.log[end.saturating_sub(VISIBLE_LINES)..end]
+    }
+
+    /// Lines newer than what's on screen
+    pub fn scrolled(&self) -> usize
+    {
+        self.scroll
+    }
+
+    pub fn page_up(&mut self)
+    {
+        let top = self.log.len().saturating_sub(VISIBLE_LINES);
+        self.scroll = (self.scroll + VISIBLE_LINES - 1).min(top);
+    }
+
+    pub fn page_down(&mut self)
+    {
+        self.scroll = self.scroll.saturating_sub(VISIBLE_LINES - 1);
+    }
+
+    pub fn push_char(&mut self, ch: char)
+    {
+        // the toggle key itself shows up as text input too
+        if ch == '`' || ch.is_control()
+        {
+            return;
+        }
+        self.input.push(ch);
+    }
+
+    pub fn backspace(&mut self)
+    {
+        self.input.pop();
+    }
+
+    /// Walk back through previously submitted lines
+    pub fn history_prev(&mut self)
+    {
+        if self.history.is_empty()
+        {
+            return;
+        }
+        let index = match self.history_index
+        {
+            Some(0) => 0,
+            Some(i) => i - 1,
+            None => self.history.len() - 1
+        };
+        self.history_index = Some(index);
+        self.input = self.history[index].clone();
+    }
+
+    pub fn history_next(&mut self)
+    {
+        match self.history_index
+        {
+            Some(i) if i + 1 < self.history.len() => {
+                self.history_index = Some(i + 1);
+                self.input = self.history[i + 1].clone();
+            },
+            _ => {
+                self.history_index = None;
+                self.input.clear();
+            }
+        }
+    }
+
+    pub fn print(&mut self, line: &str)
+    {
+        // anything new jumps back down to the bottom
+        self.scroll = 0;
+        for l in line.lines()
+        {
+            self.log.push(l.to_string());
+        }
+        if self.log.len() > MAX_LOG_LINES
+        {
+            let overflow = self.log.len() - MAX_LOG_LINES;
+            self.log.drain(0..overflow);
+        }
+    }
+
+    /// Run whatever is in the input line
+    pub fn submit(&mut self, world: &mut World, state: &mut State)
+    {
+        let line = std::mem::take(&mut self.input);
+        let line = line.trim();
+        self.history_index = None;
+        if line.is_empty()
+        {
+            return;
+        }
+        self.history.push(line.to_string());
+        self.print(&format!("> {line}"));
+
+        match self.execute(line, world, state)
+        {
+            Ok(out) => if !out.is_empty() { self.print(&out) },
+            Err(err) => self.print(&format!("error: {err}"))
+        }
+    }
+
+    fn execute(&mut self, line: &str, world: &mut World, state: &mut State) -> Result<String, String>
+    {
+        let args: Vec<&str> = line.split_whitespace().collect();
+
+        match args.as_slice()
+        {
+            ["help"] => Ok(HELP.to_string()),
+            ["cls"] => {
+                self.log.clear();
+                self.scroll = 0;
+                Ok(String::new())
+            },
+            ["get", var] => get_var(var, world, state),
+            ["set", var, value] => set_var(var, value, world, state),
+            ["spawn"] => {
+                if spawn_next(world)
+                {
+                    Ok("spawned obstacle".to_string())
+                }
+                else
+                {
+                    Err("no obstacle pair waiting to spawn".to_string())
+                }
+            },
+            ["clear"] => {
+                reset_pool(world);
+                Ok("obstacles cleared".to_string())
+            },
+            ["debug", toggle] => set_debug(toggle, None, world),
+            ["debug", toggle, value] => set_debug(toggle, Some(parse_bool(value)?), world),
+            ["validate"] => validate_courses(world, "5", "20"),
+            ["validate", courses] => validate_courses(world, courses, "20"),
+            ["validate", courses, length] => validate_courses(world, courses, length),
+            ["autopilot"] => {
+                let pilots = world.read_storage::<autopilot::Pilot>();
+                Ok(pilots.join().map(|p| p.name()).collect::<Vec<&str>>().join(", "))
+            },
+            ["autopilot", name] => autopilot::set_pilot(world, name),
+            ["policy"] => Ok(format!("{} states recorded", world.read_resource::<autopilot::PolicyTable>().len())),
+            ["policy", "save"] => world.read_resource::<autopilot::PolicyTable>().save(),
+            ["policy", "clear"] => {
+                world.write_resource::<autopilot::PolicyTable>().clear();
+                Ok("policy cleared".to_string())
+            },
+            ["evolve"] => {
+                let population = world.read_resource::<evolution::Population>();
+                if !population.active
+                {
+                    return Ok("not training".to_string());
+                }
+                Ok(format!("generation {}, {}/{} alive, last best fitness {:.0}",
+                    population.generation, population.alive(world), population.members.len(), population.best))
+            },
+            ["evolve", "off"] => {
+                let errors = evolution::stop(world);
+                if !errors.is_empty()
+                {
+                    return Err(errors.join("\n"));
+                }
+                Ok("training stopped".to_string())
+            },
+            ["evolve", size] => {
+                let size = size.parse::<usize>().map_err(|e| format!("'{size}' isn't a population size ({e})"))?;
+                evolution::start(world, size)
+            },
+            ["freeze"] => {
+                let mut time = world.write_resource::<TimeScale>();
+                time.frozen = !time.frozen;
+                Ok(if time.frozen { "frozen" } else { "unfrozen" }.to_string())
+            },
+            ["step"] => {
+                let mut time = world.write_resource::<TimeScale>();
+                if !time.frozen
+                {
+                    return Err("not frozen, freeze first".to_string());
+                }
+                time.step();
+                Ok(String::new())
+            },
+            ["collider"] => Ok(describe_collider(world)),
+            ["collider", "mask", value] => {
+                let value = parse_bool(value)?;
+                set_bird_collider(world, |c| c.use_mask = value);
+                Ok(describe_collider(world))
+            },
+            ["collider", shape @ ..] => {
+                let shape = collider::Shape::parse(shape)?;
+                set_bird_collider(world, |c| c.shape = shape);
+                Ok(describe_collider(world))
+            },
+            ["levels"] => {
+                let list = world.read_resource::<levels::LevelList>();
+                let names = std::iter::once("endless".to_string())
+                    .chain(list.levels.iter().map(|l| format!("{} ({}, {} pipes)", l.name, l.file, l.pipes.len())));
+                Ok(names.enumerate()
+                    .map(|(i, name)| format!("{} {name}", if i == list.selected { "*" } else { " " }))
+                    .collect::<Vec<String>>()
+                    .join("\n"))
+            },
+            ["level", name @ ..] if !name.is_empty() => {
+                let name = name.join(" ");
+                let mut list = world.write_resource::<levels::LevelList>();
+                if !list.select(&name)
+                {
+                    return Err(format!("no level called '{name}'"));
+                }
+                Ok(format!("{} selected, starts next time you play", list.selected_name()))
+            },
+            ["dump"] => Ok(dump_entities(world)),
+            ["dump", component] => dump_component(component, world),
+            _ => Err(format!("unknown command '{line}', try help"))
+        }
+    }
+}
+
+fn parse_f32(value: &str) -> Result<f32, String>
+{
+    match value.parse::<f32>()
+    {
+        Ok(num) if num.is_finite() => Ok(num),
+        Ok(_) => Err(format!("'{value}' isn't a finite number")),
+        Err(e) => Err(format!("'{value}' isn't a number ({e})"))
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String>
+{
+    match value
+    {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => Err(format!("'{value}' should be on or off"))
+    }
+}
+
+fn state_name(state: &State) -> &'static str
+{
+    match state
+    {
+        State::Menu => "menu",
+        State::Playing => "playing",
+        State::GameOver => "gameover",
+        State::LevelComplete => "complete",
+        State::Editor => "editor"
+    }
+}
+
+fn get_var(var: &str, world: &World, state: &State) -> Result<String, String>
+{
+    if let Some(value) = tuning::get(world, var)
+    {
+        return Ok(format!("{var} = {value}"));
+    }
+
+    let value = match var
+    {
+        "timescale" => world.read_resource::<TimeScale>().scale.to_string(),
+        var if mixer::VOLUME_VARS.contains(&var) => {
+            world.read_resource::<mixer::AudioManager>().volumes.get(var).unwrap_or_default().to_string()
+        },
+        "score" => world.read_resource::<Score>().0.to_string(),
+        "difficulty" => world.read_resource::<Difficulty>().0.to_string(),
+        "multiplier" => world.read_resource::<scoring::ScoreMultipliers>().total().to_string(),
+        "combo" => {
+            let combo = world.read_resource::<scoring::Combo>();
+            format!("{} (best {}, x{})", combo.count, combo.best, combo.factor())
+        },
+        "scoring" => world.read_resource::<scoring::ScoreStats>().to_string(),
+        "pattern" => world.read_resource::<course::Course>().pattern_name().unwrap_or("none").to_string(),
+        "state" => state_name(state).to_string(),
+        "impact" => {
+            let stats = world.read_resource::<CollisionStats>();
+            match stats.last
+            {
+                Some(e) => format!("{} hits, last {:?} ({}) at {:.0},{:.0} normal {:.2},{:.2}, {:.2} into the tick",
+                    stats.hits, e.kind, e.other.id(), e.point.x, e.point.y, e.normal.x, e.normal.y, e.toi),
+                None => "nothing hit yet".to_string()
+            }
+        },
+        _ => return Err(format!("unknown var '{var}'"))
+    };
+    Ok(format!("{var} = {value}"))
+}
+
+fn set_var(var: &str, value: &str, world: &mut World, state: &mut State) -> Result<String, String>
+{
+    match var
+    {
+        var if tuning::TUNING_VARS.contains(&var) => tuning::set(world, var, parse_f32(value)?)?,
+        "timescale" => world.write_resource::<TimeScale>().set(parse_f32(value)?),
+        var if mixer::VOLUME_VARS.contains(&var) => {
+            world.write_resource::<mixer::AudioManager>().set_volume(var, parse_f32(value)?)?;
+        },
+        "score" => {
+            world.write_resource::<Score>().0 = value.parse::<u64>()
+                .map_err(|e| format!("'{value}' isn't a valid score ({e})"))?;
+        },
+        "difficulty" => {
+            world.write_resource::<Difficulty>().0 = value.parse::<u8>()
+                .map_err(|e| format!("'{value}' isn't a valid difficulty ({e})"))?;
+        },
+        "multiplier" => {
+            let factor = value.parse::<u64>().map_err(|e| format!("'{value}' isn't a valid multiplier ({e})"))?;
+            world.write_resource::<scoring::ScoreMultipliers>().set("console", factor);
+        },
+        "state" => {
+            *state = match value
+            {
+                "menu" => State::Menu,
+                "playing" => State::Playing,
+                "gameover" => State::GameOver,
+                _ => return Err(format!("unknown state '{value}'"))
+            };
+            // otherwise update() would flip us straight back to game over
+            world.write_resource::<IsGameover>().0 = matches!(state, State::GameOver);
+        },
+        _ => return Err(format!("unknown var '{var}'"))
+    }
+    get_var(var, world, state)
+}
+
+fn validate_courses(world: &World, courses: &str, length: &str) -> Result<String, String>
+{
+    let courses = courses.parse::<usize>().map_err(|e| format!("'{courses}' isn't a count ({e})"))?;
+    let length = length.parse::<usize>().map_err(|e| format!("'{length}' isn't a length ({e})"))?;
+
+    let physics = course::Physics::from_world(world);
+    let library = world.read_resource::<patterns::PatternLibrary>();
+    let difficulty = world.read_resource::<Difficulty>().0;
+    let width = ObstaclePair::new(SPAWN_X, WINDOW_H/2.0).width;
+    for n in 0..courses
+    {
+        let seed = rand::random();
+        let gaps = course::generate(length, &library, difficulty, width, &physics, seed);
+        if let Err(index) = course::validate(&gaps, width, &physics)
+        {
+            let gaps: Vec<String> = gaps.iter().map(|g| format!("{:.0}", g.center)).collect();
+            return Err(format!("course {n} (seed {seed}) is stuck at gap {index}: {}", gaps.join(" ")));
+        }
+    }
+    Ok(format!("all {courses} courses of {length} gaps are passable"))
+}
+
+fn set_debug(toggle: &str, value: Option<bool>, world: &mut World) -> Result<String, String>
+{
+    let mut debug = world.write_resource::<DebugSettings>();
+    let setting = match toggle
+    {
+        "hitboxes" => &mut debug.show_hitboxes,
+        "movement" => &mut debug.run_movement,
+        "collision" => &mut debug.run_collision,
+        "obstacles" => &mut debug.run_obstacles,
+        "god" => &mut debug.god_mode,
+        _ => return Err(format!("unknown debug toggle '{toggle}'"))
+    };
+    *setting = value.unwrap_or(!*setting);
+    Ok(format!("{toggle} {}", if *setting { "on" } else { "off" }))
+}
+
+fn set_bird_collider(world: &mut World, change: impl Fn(&mut collider::Collider))
+{
+    let mut colliders = world.write_storage::<collider::Collider>();
+    let control = world.read_storage::<Controllable>();
+    for (c, _) in (&mut colliders, &control).join()
+    {
+        change(c);
+    }
+}
+
+fn describe_collider(world: &World) -> String
+{
+    let colliders = world.read_storage::<collider::Collider>();
+    let control = world.read_storage::<Controllable>();
+    (&colliders, &control).join()
+        .map(|(c, _)| format!("{:?}, mask {}", c.shape,
+            match (&c.mask, c.use_mask)
+            {
+                (None, _) => "missing",
+                (Some(_), true) => "on",
+                (Some(_), false) => "off"
+            }))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn dump_entities(world: &World) -> String
+{
+    let entities = world.entities();
+    let rect = world.read_storage::<Rect>();
+    let velo = world.read_storage::<Velocity>();
+    let pairs = world.read_storage::<ObstaclePair>();
+    let hitboxes = world.read_storage::<ObstacleHitbox>();
+    let gates = world.read_storage::<ObstacleGate>();
+    let triggers = world.read_storage::<triggers::Trigger>();
+    let states = world.read_storage::<ObstacleState>();
+    let oscillate = world.read_storage::<behaviours::Oscillate>();
+    let breathe = world.read_storage::<behaviours::Breathe>();
+    let drop = world.read_storage::<behaviours::DropIn>();
+    let collision = world.read_storage::<Collision>();
+    let colliders = world.read_storage::<collider::Collider>();
+    let control = world.read_storage::<Controllable>();
+    let pilots = world.read_storage::<autopilot::Pilot>();
+    let dead = world.read_storage::<Dead>();
+    let progress = world.read_storage::<Progress>();
+    let animation = world.read_storage::<Animation>();
+
+    let mut out = Vec::new();
+    for ent in entities.join()
+    {
+        let mut components = Vec::new();
+        if rect.contains(ent) { components.push("Rect".to_string()); }
+        if velo.contains(ent) { components.push("Velocity".to_string()); }
+        if pairs.contains(ent) { components.push("ObstaclePair".to_string()); }
+        if let Some(h) = hitboxes.get(ent) { components.push(format!("ObstacleHitbox({:?} of {})", h.part, h.pair.id())); }
+        if let Some(g) = gates.get(ent) { components.push(format!("ObstacleGate(of {})", g.pair.id())); }
+        if let Some(t) = triggers.get(ent) { components.push(format!("Trigger({:?}, {} inside)", t.kind, t.inside.len())); }
+        if let Some(s) = states.get(ent) { components.push(format!("ObstacleState({s:?})")); }
+        if oscillate.contains(ent) { components.push("Oscillate".to_string()); }
+        if breathe.contains(ent) { components.push("Breathe".to_string()); }
+        if drop.contains(ent) { components.push("DropIn".to_string()); }
+        if collision.contains(ent) { components.push("Collision".to_string()); }
+        if let Some(c) = colliders.get(ent) { components.push(format!("Collider({:?})", c.shape)); }
+        if control.contains(ent) { components.push("Controllable".to_string()); }
+        if let Some(p) = pilots.get(ent) { components.push(format!("Pilot({})", p.name())); }
+        if dead.contains(ent) { components.push("Dead".to_string()); }
+        if let Some(p) = progress.get(ent) { components.push(format!("Progress({} frames, {} gaps)", p.frames, p.gaps)); }
+        if animation.contains(ent) { components.push("Animation".to_string()); }
+        out.push(format!("{:>3}: {}", ent.id(), components.join(", ")));
+    }
+    out.join("\n")
+}
+
+fn dump_component(component: &str, world: &World) -> Result<String, String>
+{
+    let entities = world.entities();
+    let mut out = Vec::new();
+    match component
+    {
+        "entities" => return Ok(dump_entities(world)),
+        "rect" => {
+            for (ent, r) in (&entities, &world.read_storage::<Rect>()).join()
+            {
+                out.push(format!("{:>3}: {:?}", ent.id(), r));
+            }
+        },
+        "velocity" => {
+            for (ent, v) in (&entities, &world.read_storage::<Velocity>()).join()
+            {
+                out.push(format!("{:>3}: {:?}", ent.id(), v));
+            }
+        },
+        "obstacle" => {
+            for (ent, pair) in (&entities, &world.read_storage::<ObstaclePair>()).join()
+            {
+                out.push(format!("{:>3}: {:?}", ent.id(), pair));
+            }
+        },
+        _ => return Err(format!("unknown component '{component}'"))
+    }
+    Ok(out.join("\n"))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn help_fits()
+    {
+        // plus the "> help" echo
+        assert!(HELP.lines().count() < VISIBLE_LINES, "help is {} lines", HELP.lines().count());
+    }
+
+    #[test]
+    fn scrollback()
+    {
+        let mut console = Console::default();
+        for n in 0..100
+        {
+            console.print(&n.to_string());
+        }
+        assert_eq!(console.visible().last().map(String::as_str), Some("99"));
+
+        console.page_up();
+        assert_eq!(console.visible().last().map(String::as_str), Some(&*(99 - VISIBLE_LINES + 1).to_string()));
+        for _ in 0..10
+        {
+            console.page_up();
+        }
+        assert_eq!(console.visible().first().map(String::as_str), Some("0"));
+        assert_eq!(console.visible().len(), VISIBLE_LINES);
+
+        console.print("new");
+        assert_eq!(console.scrolled(), 0);
+        assert_eq!(console.visible().last().map(String::as_str), Some("new"));
+    }
+}
@@ -0,0 +1,368 @@
+use rand::*;
+use rand::rngs::StdRng;
+use specs::*;
+
+use crate::*;
+use crate::behaviours::Motion;
+use crate::patterns::{Pattern, PatternLibrary, Step};
+
+/// The physics are stepped once per frame, gravity is scaled by delta so we assume 60fps
+pub const NOMINAL_DT:f32 = 1.0/60.0;
+
+/// How much of the bird has to fit into the reachable part of a gap
+const PASS_MARGIN:f32 = 10.0;
+
+// size of the buckets validate() sorts bird states into
+const BUCKET_Y:f32 = 2.0;
+const BUCKET_VEL:f32 = 0.5;
+const VEL_BUCKETS:usize = 160;
+
+/// Obstacles have to go left at least this fast, slower and they'd never get to the bird
+pub const MIN_SPEED:f32 = 0.5;
+
+// ceiling and floor hitboxes, see GameState::new
+pub const CEILING_Y:f32 = SQUARE_SIZE/2.0 - 1.0;
+pub const FLOOR_Y:f32 = WINDOW_H - SQUARE_SIZE/2.0;
+
+/// Snapshot of everything that decides where the bird can get to
+#[derive(Clone, Copy, Debug)]
+pub struct Physics
+{
+    pub gravity: f32,
+    pub flap: f32,
+    pub speed: f32,
+    pub spacing: f32
+}
+
+impl Physics
+{
+    pub fn from_world(world: &World) -> Physics
+    {
+        Physics {
+            gravity: world.read_resource::<Gravity>().0,
+            flap: world.read_resource::<FlapStrength>().0,
+            speed: world.read_resource::<ObstacleSpeed>().0,
+            spacing: world.read_resource::<obstacles::ObstaclePool>().spacing
+        }
+    }
+
+    /// One frame of bird movement, the same order the dispatcher does it in
+    pub fn step(&self, y: f32, vel_y: f32, flap: bool) -> (f32, f32)
+    {
+        let vel_y = if flap { flap_velocity(vel_y, self.flap) } else { vel_y };
+        let vel_y = vel_y + self.gravity * NOMINAL_DT;
+        (y + vel_y, vel_y)
+    }
+
+    /// Frames the bird spends between leaving one pair and reaching the next
+    fn free_frames(&self, width: f32) -> usize
+    {
+        if self.speed.abs() < MIN_SPEED
+        {
+            return 0;
+        }
+        ((self.spacing - width - SQUARE_SIZE) / self.speed.abs()).max(0.0) as usize
+    }
+}
+
+/// Top-y band the bird can reach by the next pair when it leaves a gap under control
+/// Falling is without flapping, climbing is a perfect player flapping whenever it's slower than one flap
+pub fn reachable_band(gap_center: f32, gap_size: f32, width: f32, physics: &Physics) -> (f32, f32)
+{
+    let frames = physics.free_frames(width);
+
+    let (mut low, mut low_vel) = (gap_center + gap_size/2.0 - SQUARE_SIZE, 0.0);
+    let (mut high, mut high_vel) = (gap_center - gap_size/2.0, 0.0);
+    for _ in 0..frames
+    {
+        (low, low_vel) = physics.step(low, low_vel, false);
+        (high, high_vel) = physics.step(high, high_vel, high_vel > -physics.flap);
+    }
+
+    (high.max(CEILING_Y), low.min(FLOOR_Y - SQUARE_SIZE))
+}
+
+/// One generated gap, spacing is the distance from the pair before
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gap
+{
+    pub center: f32,
+    pub size: f32,
+    pub spacing: f32,
+    pub motion: Motion
+}
+
+impl Gap
+{
+    /// Moving gaps are only counted by the part that's open the whole time
+    pub fn tightest_size(&self) -> f32
+    {
+        self.motion.tightest(self.size)
+    }
+}
+
+/// Range the next gap center can be rolled in so a perfect player can still make it
+fn next_gap_range(last_gap: Option<Gap>, gap_size: f32, motion: Motion, width: f32, physics: &Physics) -> (f32, f32)
+{
+    // moving gaps have to stay on screen the whole way
+    let (low, high) = ((WINDOW_H/2.0) * RNG_LOW + motion.reach(), (WINDOW_H/2.0) * RNG_HIGH - motion.reach());
+    let gap_size = motion.tightest(gap_size);
+    let Some(last_gap) = last_gap else {
+        return (low, high);
+    };
+
+    // the gap has to overlap what's reachable by at least the bird plus some margin
+    let (reach_top, reach_bottom) = reachable_band(last_gap.center, last_gap.tightest_size(), width, physics);
+    let min = reach_top + SQUARE_SIZE + PASS_MARGIN - gap_size/2.0;
+    let max = reach_bottom - PASS_MARGIN + gap_size/2.0;
+
+    let (min, max) = (min.max(low), max.min(high));
+    if min > max
+    {
+        // whatever the tuning is, the same height again is always doable
+        let center = last_gap.center.clamp(low, high);
+        return (center, center);
+    }
+    (min, max)
+}
+
+/// Rolls gaps one after the other following patterns from the library,
+/// each gap gets pulled into what's reachable from the last one
+#[derive(Clone, Debug)]
+pub struct Course
+{
+    last_gap: Option<Gap>,
+    pattern: Option<Pattern>,
+    step: usize,
+    /// every roll comes out of this, so a seeded course is the same every time
+    rng: StdRng
+}
+
+impl Default for Course
+{
+    fn default() -> Self
+    {
+        Course {
+            last_gap: None,
+            pattern: None,
+            step: 0,
+            rng: StdRng::from_entropy()
+        }
+    }
+}
+
+impl Course
+{
+    /// The step the next pair will use, picks a new pattern when the current one is done
+    fn upcoming(&mut self, library: &PatternLibrary, difficulty: u8) -> Step
+    {
+        let done = match &self.pattern
+        {
+            Some(pattern) => self.step >= pattern.steps.len(),
+            None => true
+        };
+        if done
+        {
+            self.pattern = library.pick(difficulty, &mut self.rng).cloned();
+            self.step = 0;
+        }
+
+        match &self.pattern
+        {
+            Some(pattern) => pattern.steps[self.step],
+            None => Step::default()
+        }
+    }
+
+    pub fn pattern_name(&self) -> Option<&str>
+    {
+        self.pattern.as_ref().map(|p| p.name.as_str())
+    }
+
+    /// How far behind the last pair the next one should come in
+    pub fn next_spacing(&mut self, library: &PatternLibrary, difficulty: u8, spacing: f32) -> f32
+    {
+        spacing * self.upcoming(library, difficulty).spacing
+    }
+
+    pub fn next_gap(&mut self, library: &PatternLibrary, difficulty: u8, width: f32, physics: &Physics) -> Gap
+    {
+        let step = self.upcoming(library, difficulty);
+        let physics = Physics { spacing: physics.spacing * step.spacing, ..*physics };
+
+        let (min, max) = next_gap_range(self.last_gap, step.gap_size, step.motion, width, &physics);
+        let center = match (step.offset, self.last_gap)
+        {
+            (Some(offset), Some(last_gap)) => (last_gap.center + offset).clamp(min, max),
+            _ if min < max => self.rng.gen_range::<f32, f32, f32>(min, max),
+            _ => min
+        };
+
+        let gap = Gap {
+            center,
+            size: step.gap_size,
+            spacing: physics.spacing,
+            motion: step.motion
+        };
+        self.last_gap = Some(gap);
+        self.step += 1;
+        gap
+    }
+
+    pub fn reset(&mut self)
+    {
+        *self = Course::default();
+    }
+
+    /// Roll the same course every time from here on
+    pub fn reseed(&mut self, seed: u64)
+    {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+}
+
+/// Generate a course the same way the game does, the same seed gives the same course
+pub fn generate(length: usize, library: &PatternLibrary, difficulty: u8, width: f32, physics: &Physics, seed: u64) -> Vec<Gap>
+{
+    let mut course = Course::default();
+    course.reseed(seed);
+    (0..length).map(|_| course.next_gap(library, difficulty, width, physics)).collect()
+}
+
+/// Proves a course can be flown by searching flap sequences frame by frame with the real
+/// flap rule and hitboxes. States are bucketed so only one real state per bucket is kept,
+/// which means everything found is actually reachable. Returns the index of the first gap
+/// no flap sequence gets through
+pub fn validate(gaps: &[Gap], width: f32, physics: &Physics) -> Result<(), usize>
+{
+    let speed = physics.speed.abs();
+    if speed < MIN_SPEED
+    {
+        // nothing would ever get to the bird, so nothing's ever passed
+        return if gaps.is_empty() { Ok(()) } else { Err(0) };
+    }
+    // where each pair starts out, the first one comes in at the spawn point
+    let start_x: Vec<f32> = gaps.iter()
+        .scan(None, |x: &mut Option<f32>, gap| {
+            let next = x.map_or(obstacles::SPAWN_X, |x| x + gap.spacing);
+            *x = Some(next);
+            Some(next)
+        })
+        .collect();
+    let pair_x = |index: usize, frame: usize| start_x[index] - frame as f32 * speed;
+
+    // seen[bucket] == frame means that bucket already has a state this frame
+    let y_buckets = (WINDOW_H / BUCKET_Y) as usize + 1;
+    let mut seen = vec![0usize; y_buckets * VEL_BUCKETS];
+    let bucket = |y: f32, vel_y: f32| {
+        let vel = ((vel_y / BUCKET_VEL) as i32 + VEL_BUCKETS as i32/2).clamp(0, VEL_BUCKETS as i32 - 1);
+        (y / BUCKET_Y) as usize * VEL_BUCKETS + vel as usize
+    };
+
+    let mut states = vec![(BIRD_Y, 0.0_f32)];
+    let mut frame = 0;
+    loop
+    {
+        // the first pair the bird hasn't fully gotten by yet
+        let next = (0..gaps.len()).find(|i| pair_x(*i, frame) + width > BIRD_X);
+        let Some(next) = next else {
+            return Ok(());
+        };
+        if states.is_empty()
+        {
+            return Err(next);
+        }
+
+        frame += 1;
+        let mut next_states = Vec::new();
+        for (y, vel_y) in states
+        {
+            for flap in [false, true]
+            {
+                // flapping while already rising faster than two flaps only gets you into the ceiling
+                if flap && vel_y < -2.0*physics.flap
+                {
+                    continue;
+                }
+                let (y, vel_y) = physics.step(y, vel_y, flap);
+                if y < CEILING_Y || y + SQUARE_SIZE > FLOOR_Y
+                {
+                    continue;
+                }
+
+                let hits_pair = (next..gaps.len())
+                    .take_while(|i| pair_x(*i, frame) < BIRD_X + SQUARE_SIZE)
+                    .any(|i| {
+                        let x = pair_x(i, frame);
+                        let size = gaps[i].tightest_size();
+                        x + width > BIRD_X
+                            && (y < gaps[i].center - size/2.0
+                                || y + SQUARE_SIZE > gaps[i].center + size/2.0)
+                    });
+                if hits_pair
+                {
+                    continue;
+                }
+
+                let b = bucket(y, vel_y);
+                if seen[b] != frame
+                {
+                    seen[b] = frame;
+                    next_states.push((y, vel_y));
+                }
+            }
+        }
+        states = next_states;
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::obstacles::{ObstaclePair, OBSTACLE_SPACING, SPAWN_X};
+
+    fn physics() -> Physics
+    {
+        Physics { gravity: GRAVITY, flap: BIRD_FLAP, speed: OBST_SPEED, spacing: OBSTACLE_SPACING }
+    }
+
+    fn assert_passable(library: &PatternLibrary, physics: &Physics)
+    {
+        let width = ObstaclePair::new(SPAWN_X, WINDOW_H/2.0).width;
+        // one past the last difficulty anything unlocks at, so the growth weights get a go too
+        let max_difficulty = library.patterns.iter().map(|p| p.difficulty).max().unwrap_or(0) + 1;
+        for difficulty in 0..=max_difficulty
+        {
+            for seed in 0..8
+            {
+                let gaps = generate(20, library, difficulty, width, physics, seed);
+                assert_eq!(validate(&gaps, width, physics), Ok(()), "seed {seed} at difficulty {difficulty} gets stuck");
+            }
+        }
+    }
+
+    #[test]
+    fn default_patterns_are_passable()
+    {
+        assert_passable(&PatternLibrary::default(), &physics());
+    }
+
+    #[test]
+    fn asset_patterns_are_passable()
+    {
+        let contents = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/patterns.cfg")).unwrap();
+        let (library, errors) = PatternLibrary::parse(&contents);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_passable(&library, &physics());
+    }
+
+    #[test]
+    fn stopped_obstacles_give_up()
+    {
+        let physics = Physics { speed: 0.0, ..physics() };
+        let gaps = generate(5, &PatternLibrary::default(), 0, SQUARE_SIZE, &physics, 0);
+        assert_eq!(validate(&gaps, SQUARE_SIZE, &physics), Err(0));
+    }
+}
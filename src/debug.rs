@@ -0,0 +1,154 @@
+use ggez::event::KeyCode;
+
+/// Debug toggles that can be flipped at runtime with the function keys
+/// F1 hitboxes, F2 movement, F3 collision, F4 obstacles, F5 god mode
+#[derive(Clone, Copy, Debug)]
+pub struct DebugSettings
+{
+    pub show_hitboxes: bool,
+    pub run_movement: bool,
+    pub run_collision: bool,
+    pub run_obstacles: bool,
+    /// collisions are still detected, they just don't end the game
+    pub god_mode: bool,
+}
+
+impl Default for DebugSettings
+{
+    fn default() -> Self
+    {
+        DebugSettings {
+            show_hitboxes: false,
+            run_movement: true,
+            run_collision: true,
+            run_obstacles: true,
+            god_mode: false,
+        }
+    }
+}
+
+impl DebugSettings
+{
+    /// Flip whatever toggle is bound to the key, returns false if the key isn't bound
+    pub fn toggle(&mut self, key: KeyCode) -> bool
+    {
+        let toggle = match key
+        {
+            KeyCode::F1 => &mut self.show_hitboxes,
+            KeyCode::F2 => &mut self.run_movement,
+            KeyCode::F3 => &mut self.run_collision,
+            KeyCode::F4 => &mut self.run_obstacles,
+            KeyCode::F5 => &mut self.god_mode,
+            _ => return false
+        };
+        *toggle = !*toggle;
+        true
+    }
+
+    /// Text for the on-screen indicator, None if nothing differs from a normal run
+    pub fn indicator(&self) -> Option<String>
+    {
+        let mut enabled = Vec::new();
+        if self.show_hitboxes { enabled.push("HITBOXES"); }
+        if !self.run_movement { enabled.push("MOVEMENT OFF"); }
+        if !self.run_collision { enabled.push("COLLISION OFF"); }
+        if !self.run_obstacles { enabled.push("OBSTACLES OFF"); }
+        if self.god_mode { enabled.push("GOD MODE"); }
+
+        if enabled.is_empty()
+        {
+            return None;
+        }
+        Some(format!("DEBUG: {}", enabled.join(" | ")))
+    }
+}
+
+const TIME_SCALES:[f32; 7] = [0.1, 0.25, 0.5, 1.0, 2.0, 3.0, 4.0];
+
+/// Scales how fast the simulation runs, and lets it be frozen and stepped one tick at a time
+/// F6 slower, F7 faster, F8 freeze, F9 step while frozen
+#[derive(Clone, Copy, Debug)]
+pub struct TimeScale
+{
+    pub scale: f32,
+    pub frozen: bool,
+    step_pending: bool,
+}
+
+impl Default for TimeScale
+{
+    fn default() -> Self
+    {
+        TimeScale {
+            scale: 1.0,
+            frozen: false,
+            step_pending: false,
+        }
+    }
+}
+
+impl TimeScale
+{
+    pub fn set(&mut self, scale: f32)
+    {
+        self.scale = scale.clamp(TIME_SCALES[0], TIME_SCALES[TIME_SCALES.len() - 1]);
+    }
+
+    pub fn slower(&mut self)
+    {
+        if let Some(scale) = TIME_SCALES.iter().rev().find(|s| **s < self.scale)
+        {
+            self.scale = *scale;
+        }
+    }
+
+    pub fn faster(&mut self)
+    {
+        if let Some(scale) = TIME_SCALES.iter().find(|s| **s > self.scale)
+        {
+            self.scale = *scale;
+        }
+    }
+
+    /// Queue up exactly one tick, only does anything while frozen
+    pub fn step(&mut self)
+    {
+        self.step_pending = self.frozen;
+    }
+
+    /// Should the simulation run this frame, uses up a queued step
+    pub fn should_tick(&mut self) -> bool
+    {
+        if !self.frozen
+        {
+            return true;
+        }
+        std::mem::take(&mut self.step_pending)
+    }
+
+    pub fn handle_key(&mut self, key: KeyCode) -> bool
+    {
+        match key
+        {
+            KeyCode::F6 => self.slower(),
+            KeyCode::F7 => self.faster(),
+            KeyCode::F8 => {
+                self.frozen = !self.frozen;
+                self.step_pending = false;
+            },
+            KeyCode::F9 => self.step(),
+            _ => return false
+        }
+        true
+    }
+
+    pub fn indicator(&self) -> Option<String>
+    {
+        match (self.frozen, self.scale == 1.0)
+        {
+            (false, true) => None,
+            (false, false) => Some(format!("TIME x{}", self.scale)),
+            (true, _) => Some(format!("FROZEN (F9 STEP) x{}", self.scale))
+        }
+    }
+}
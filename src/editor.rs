@@ -0,0 +1,375 @@
+use std::path::PathBuf;
+
+use ggez::event::{KeyCode, KeyMods, MouseButton};
+use ggez::graphics::{self, Color, DrawMode, DrawParam, MeshBuilder};
+use ggez::{Context, GameResult};
+
+use crate::*;
+use crate::behaviours::Motion;
+use crate::course::Gap;
+use crate::levels::{Level, LevelPipe, LEVEL_DIR};
+
+/// How close to a gap edge a click has to be to resize instead of move
+const EDGE_GRAB:f32 = 12.0;
+/// Positions snap to this so the saved files stay readable
+const SNAP:f32 = 10.0;
+/// Distance the arrow keys scrub, page up/down scrub a whole screen
+const SCRUB_STEP:f32 = 50.0;
+const MIN_GAP:f32 = SQUARE_SIZE;
+
+const HELP:&str = "\
+CLICK place/drag pipe, drag gap edges to resize, RIGHT CLICK delete
+ARROWS/WHEEL/PGUP/PGDN scrub, HOME start, M motion, F finish at mouse
+P play from here, S save, ESC menu";
+
+/// What the game has to do after the editor handled a key
+pub enum EditorAction
+{
+    None,
+    Message(String),
+    Playtest,
+    Save,
+    Exit
+}
+
+enum Drag
+{
+    /// offset from the grab point to the pipe's x and gap center
+    Move { dx: f32, dy: f32 },
+    Top,
+    Bottom
+}
+
+/// Mouse driven editor for level files, the course is scrolled so the bird's
+/// column is at `scroll` distance, the same point play testing starts from
+pub struct Editor
+{
+    pub level: Level,
+    pub scroll: f32,
+    pub dirty: bool,
+    /// set while a play test started from here is running
+    pub playtesting: bool,
+    path: PathBuf,
+    selected: Option<usize>,
+    drag: Option<Drag>,
+    mouse: (f32, f32),
+    pipe_width: f32,
+    /// esc was pressed once with unsaved changes
+    exit_warned: bool
+}
+
+impl Editor
+{
+    /// Edit a level, asset_dir is where its file gets saved to
+    pub fn new(level: Level, asset_dir: &std::path::Path) -> Editor
+    {
+        Editor {
+            path: asset_dir.join(level.file.trim_start_matches('/')),
+            level,
+            scroll: 0.0,
+            dirty: false,
+            playtesting: false,
+            selected: None,
+            drag: None,
+            mouse: (0.0, 0.0),
+            pipe_width: obstacles::ObstaclePair::new(0.0, 0.0).width,
+            exit_warned: false
+        }
+    }
+
+    /// A fresh level in a file that isn't taken yet
+    pub fn new_level(asset_dir: &std::path::Path) -> Editor
+    {
+        let file = (1..)
+            .map(|n| format!("{LEVEL_DIR}/{n:02}_new.lvl"))
+            .find(|file| !asset_dir.join(file.trim_start_matches('/')).exists())
+            .unwrap_or_default();
+
+        let mut level = Level::new(&file);
+        level.finish = 2.0 * WINDOW_W;
+        let mut editor = Editor::new(level, asset_dir);
+        editor.dirty = true;
+        editor
+    }
+
+    fn screen_x(&self, x: f32) -> f32
+    {
+        BIRD_X + x - self.scroll
+    }
+
+    fn course_x(&self, screen_x: f32) -> f32
+    {
+        screen_x - BIRD_X + self.scroll
+    }
+
+    fn pipe_at(&self, x: f32) -> Option<usize>
+    {
+        self.level.pipes.iter().rposition(|p| {
+            let left = self.screen_x(p.x);
+            x >= left && x <= left + self.pipe_width
+        })
+    }
+
+    pub fn mouse_down(&mut self, button: MouseButton, x: f32, y: f32)
+    {
+        match button
+        {
+            MouseButton::Left => {
+                let Some(index) = self.pipe_at(x) else {
+                    // nothing there, put a new pipe down centered on the mouse
+                    self.level.pipes.push(LevelPipe {
+                        x: snap(self.course_x(x - self.pipe_width/2.0)),
+                        gap: Gap {
+                            center: snap(y),
+                            size: OBSTACLE_TIGTHFACTOR,
+                            spacing: 0.0,
+                            motion: Motion::Still
+                        }
+                    });
+                    self.selected = Some(self.level.pipes.len() - 1);
+                    self.drag = None;
+                    self.dirty = true;
+                    return;
+                };
+
+                let gap = self.level.pipes[index].gap;
+                let (top, bottom) = (gap.center - gap.size/2.0, gap.center + gap.size/2.0);
+                self.selected = Some(index);
+                self.drag = Some(if (y - top).abs() < EDGE_GRAB
+                {
+                    Drag::Top
+                }
+                else if (y - bottom).abs() < EDGE_GRAB
+                {
+                    Drag::Bottom
+                }
+                else
+                {
+                    Drag::Move {
+                        dx: self.level.pipes[index].x - self.course_x(x),
+                        dy: gap.center - y
+                    }
+                });
+            },
+            MouseButton::Right => {
+                if let Some(index) = self.pipe_at(x)
+                {
+                    self.level.pipes.remove(index);
+                    self.selected = None;
+                    self.dirty = true;
+                }
+            },
+            _ => {}
+        }
+    }
+
+    pub fn mouse_up(&mut self)
+    {
+        self.drag = None;
+    }
+
+    pub fn mouse_moved(&mut self, x: f32, y: f32)
+    {
+        self.mouse = (x, y);
+        let (Some(drag), Some(index)) = (&self.drag, self.selected) else {
+            return;
+        };
+
+        let course_x = self.course_x(x);
+        let pipe = &mut self.level.pipes[index];
+        let (top, bottom) = (pipe.gap.center - pipe.gap.size/2.0, pipe.gap.center + pipe.gap.size/2.0);
+        let (top, bottom) = match drag
+        {
+            Drag::Move { dx, dy } => {
+                pipe.x = snap(course_x + dx).max(0.0);
+                let center = snap(y + dy);
+                (center - pipe.gap.size/2.0, center + pipe.gap.size/2.0)
+            },
+            // the other edge stays where it is
+            Drag::Top => (snap(y).min(bottom - MIN_GAP), bottom),
+            Drag::Bottom => (top, snap(y).max(top + MIN_GAP))
+        };
+        pipe.gap.center = (top + bottom) / 2.0;
+        pipe.gap.size = bottom - top;
+        self.dirty = true;
+    }
+
+    pub fn wheel(&mut self, y: f32)
+    {
+        self.scrub(-y * SCRUB_STEP * 2.0);
+    }
+
+    fn scrub(&mut self, distance: f32)
+    {
+        self.scroll = (self.scroll + distance).max(0.0);
+    }
+
+    pub fn key(&mut self, key: KeyCode, mods: KeyMods, repeat: bool) -> EditorAction
+    {
+        // holding a key only keeps scrubbing, it shouldn't save or toggle over and over
+        if repeat && !matches!(key, KeyCode::Left | KeyCode::Right | KeyCode::PageUp | KeyCode::PageDown)
+        {
+            return EditorAction::None;
+        }
+        let fast = if mods.contains(KeyMods::SHIFT) { 5.0 } else { 1.0 };
+        let exit_warned = std::mem::take(&mut self.exit_warned);
+        match key
+        {
+            KeyCode::Left => self.scrub(-SCRUB_STEP * fast),
+            KeyCode::Right => self.scrub(SCRUB_STEP * fast),
+            KeyCode::PageUp => self.scrub(-WINDOW_W),
+            KeyCode::PageDown => self.scrub(WINDOW_W),
+            KeyCode::Home => self.scroll = 0.0,
+            KeyCode::Delete | KeyCode::Back => {
+                if let Some(index) = self.selected.take()
+                {
+                    self.level.pipes.remove(index);
+                    self.dirty = true;
+                }
+            },
+            KeyCode::M => {
+                if let Some(index) = self.selected
+                {
+                    let gap = &mut self.level.pipes[index].gap;
+                    gap.motion = next_motion(gap.motion, gap.size);
+                    self.dirty = true;
+                }
+            },
+            KeyCode::F => {
+                self.level.finish = snap(self.course_x(self.mouse.0)).max(0.0);
+                self.dirty = true;
+            },
+            KeyCode::P => return EditorAction::Playtest,
+            KeyCode::S => return EditorAction::Save,
+            KeyCode::Escape if self.dirty && !exit_warned => {
+                self.exit_warned = true;
+                return EditorAction::Message("unsaved changes, esc again to leave anyway or s to save".to_string());
+            },
+            KeyCode::Escape => return EditorAction::Exit,
+            _ => {}
+        }
+        EditorAction::None
+    }
+
+    /// The level the way it'd be loaded from disk
+    pub fn sorted_level(&self) -> Level
+    {
+        let mut level = self.level.clone();
+        level.sort();
+        level
+    }
+
+    pub fn save(&mut self) -> Result<String, String>
+    {
+        if let Some(dir) = self.path.parent()
+        {
+            std::fs::create_dir_all(dir).map_err(|e| format!("couldn't create {}: {e}", dir.display()))?;
+        }
+        std::fs::write(&self.path, self.sorted_level().to_file_string())
+            .map_err(|e| format!("couldn't save {}: {e}", self.path.display()))?;
+        self.dirty = false;
+        Ok(format!("saved {}", self.level.file))
+    }
+
+    pub fn draw(&self, ctx: &mut Context, font: graphics::Font) -> GameResult
+    {
+        let mut mb = MeshBuilder::new();
+
+        // ruler every 500 along the course
+        let first = (self.scroll / 500.0).floor() as i32 - 1;
+        for n in first..first + (WINDOW_W / 500.0) as i32 + 3
+        {
+            let x = self.screen_x(n as f32 * 500.0);
+            mb.line(&[Point2::new(x, 0.0), Point2::new(x, WINDOW_H)], 1.0, Color::from_rgb(220, 220, 220))?;
+        }
+
+        // the bird's column is where play testing starts
+        mb.line(&[Point2::new(BIRD_X, 0.0), Point2::new(BIRD_X, WINDOW_H)], 2.0, Color::from_rgb(0, 150, 255))?;
+        mb.rectangle(DrawMode::stroke(2.0),
+            graphics::Rect::new(BIRD_X, BIRD_Y, SQUARE_SIZE, SQUARE_SIZE),
+            Color::from_rgb(0, 150, 255)
+        )?;
+
+        for event in &self.level.events
+        {
+            let x = self.screen_x(event.x);
+            mb.line(&[Point2::new(x, 0.0), Point2::new(x, WINDOW_H)], 2.0, Color::from_rgb(255, 150, 0))?;
+        }
+
+        for (i, pipe) in self.level.pipes.iter().enumerate()
+        {
+            let x = self.screen_x(pipe.x);
+            if x + self.pipe_width < 0.0 || x > WINDOW_W
+            {
+                continue;
+            }
+            let color = if self.selected == Some(i) { Color::new(1.0, 0.0, 1.0, 1.0) } else { Color::from_rgb(120, 120, 120) };
+            let (top, bottom) = (pipe.gap.center - pipe.gap.size/2.0, pipe.gap.center + pipe.gap.size/2.0);
+            mb.rectangle(DrawMode::fill(), graphics::Rect::new(x, 0.0, self.pipe_width, top.max(0.0)), color)?;
+            mb.rectangle(DrawMode::fill(), graphics::Rect::new(x, bottom, self.pipe_width, (WINDOW_H - bottom).max(0.0)), color)?;
+
+            // the part of a moving gap that's open the whole time
+            let tightest = pipe.gap.motion.tightest(pipe.gap.size);
+            if tightest < pipe.gap.size && tightest > 0.0
+            {
+                mb.rectangle(DrawMode::stroke(2.0),
+                    graphics::Rect::new(x, pipe.gap.center - tightest/2.0, self.pipe_width, tightest),
+                    Color::from_rgb(255, 150, 0)
+                )?;
+            }
+        }
+        let mesh = mb.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::default())?;
+
+        draw_finish_line(ctx, self.screen_x(self.level.finish))?;
+
+        // labels
+        let black = Color::from((0, 0, 0, 255));
+        for pipe in &self.level.pipes
+        {
+            let x = self.screen_x(pipe.x);
+            if x + self.pipe_width < 0.0 || x > WINDOW_W
+            {
+                continue;
+            }
+            let mut label = format!("{}\n{}/{}", pipe.x, pipe.gap.center, pipe.gap.size);
+            if pipe.gap.motion != Motion::Still
+            {
+                label.push_str(&format!("\n{}", pipe.gap.motion));
+            }
+            let text = graphics::Text::new((label, font, 18.0));
+            graphics::draw(ctx, &text, DrawParam::new().dest(Point2::new(x, pipe.gap.center - 25.0)).color(black))?;
+        }
+        for event in &self.level.events
+        {
+            let text = graphics::Text::new((format!("{} {}", event.var, event.value), font, 18.0));
+            graphics::draw(ctx, &text, DrawParam::new()
+                .dest(Point2::new(self.screen_x(event.x) + 4.0, WINDOW_H - 60.0))
+                .color(Color::from_rgb(255, 150, 0)))?;
+        }
+
+        let title = format!("EDITING {} ({}){}\nDISTANCE {:.0}  FINISH {:.0}  {} PIPES\n{HELP}",
+            self.level.name.to_uppercase(), self.level.file, if self.dirty { " *" } else { "" },
+            self.scroll, self.level.finish, self.level.pipes.len());
+        let text = graphics::Text::new((title, font, 22.0));
+        graphics::draw(ctx, &text, DrawParam::new().dest(Point2::new(10.0, SQUARE_SIZE/2.0 + 10.0)).color(black))?;
+        Ok(())
+    }
+}
+
+fn snap(value: f32) -> f32
+{
+    (value / SNAP).round() * SNAP
+}
+
+/// Still, oscillate, breathe, drop and round again
+fn next_motion(motion: Motion, gap_size: f32) -> Motion
+{
+    match motion
+    {
+        Motion::Still => Motion::Oscillate { amplitude: 40.0, period: 3.0 },
+        Motion::Oscillate { .. } => Motion::Breathe { min_size: snap(gap_size * 0.7).max(MIN_GAP), period: 2.0 },
+        Motion::Breathe { .. } => Motion::Drop,
+        Motion::Drop => Motion::Still
+    }
+}
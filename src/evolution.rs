@@ -0,0 +1,264 @@
+use std::path::Path;
+
+use rand::Rng;
+use specs::*;
+
+use crate::*;
+use crate::autopilot::{Observation, Pilot, Strategy};
+use crate::gym::{Env, EnvConfig, Feature};
+
+/// What every net gets to see, the same features the gym env can give out
+const INPUTS:[Feature; 5] = [Feature::BirdY, Feature::Velocity, Feature::GapDx(0), Feature::GapDy(0), Feature::GapDy(1)];
+const HIDDEN:usize = 6;
+/// Weights for the hidden layer and the output, each neuron has a bias on the end
+const WEIGHTS:usize = (INPUTS.len() + 1) * HIDDEN + HIDDEN + 1;
+
+/// A gap is worth about as much fitness as the frames it takes to get from one to the next
+const GAP_FITNESS:f32 = 100.0;
+/// The best this fraction of a generation goes on as they are
+const ELITE:f32 = 0.2;
+/// Parents come out of the top this fraction
+const PARENTS:f32 = 0.5;
+/// Chance of each weight getting nudged in a child, and by up to how much
+const MUTATION_RATE:f32 = 0.15;
+const MUTATION_SIZE:f32 = 0.5;
+/// Headless generations get cut off here, a net that's this good isn't going to die
+const MAX_FRAMES:u32 = 60 * 60 * 5;
+
+/// Tiny feed forward net, inputs to one tanh hidden layer to one output that flaps when it's over 0
+#[derive(Clone, Debug)]
+pub struct Net
+{
+    weights: Vec<f32>
+}
+
+impl Net
+{
+    pub fn random(rng: &mut impl Rng) -> Net
+    {
+        Net { weights: (0..WEIGHTS).map(|_| rng.gen_range(-1.0, 1.0)).collect() }
+    }
+
+    /// A copy with some of the weights nudged
+    pub fn mutated(&self, rng: &mut impl Rng) -> Net
+    {
+        let weights = self.weights.iter()
+            .map(|w| if rng.gen::<f32>() < MUTATION_RATE { w + rng.gen_range(-MUTATION_SIZE, MUTATION_SIZE) } else { *w })
+            .collect();
+        Net { weights }
+    }
+
+    fn output(&self, inputs: &[f32]) -> f32
+    {
+        let (hidden_weights, output_weights) = self.weights.split_at(WEIGHTS - HIDDEN - 1);
+        let hidden: Vec<f32> = hidden_weights.chunks_exact(INPUTS.len() + 1)
+            .map(|neuron| {
+                let sum: f32 = neuron.iter().zip(inputs).map(|(w, x)| w * x).sum();
+                (sum + neuron[INPUTS.len()]).tanh()
+            })
+            .collect();
+        output_weights.iter().zip(&hidden).map(|(w, h)| w * h).sum::<f32>() + output_weights[HIDDEN]
+    }
+}
+
+impl Strategy for Net
+{
+    fn name(&self) -> &'static str
+    {
+        "net"
+    }
+
+    fn decide(&mut self, obs: &Observation) -> bool
+    {
+        let inputs: Vec<f32> = INPUTS.iter().map(|f| f.value(obs)).collect();
+        self.output(&inputs) > 0.0
+    }
+}
+
+/// Birds being trained and the net flying each one
+#[derive(Default)]
+pub struct Population
+{
+    pub active: bool,
+    pub generation: u32,
+    pub members: Vec<(Entity, Net)>,
+    /// fitness of the best bird of the last generation
+    pub best: f32,
+    /// a new generation's ready, the game has to start a run for it
+    restart: bool
+}
+
+impl Population
+{
+    pub fn alive(&self, world: &World) -> usize
+    {
+        let dead = world.read_storage::<Dead>();
+        self.members.iter().filter(|(ent, _)| !dead.contains(*ent)).count()
+    }
+}
+
+fn fitness(progress: &Progress) -> f32
+{
+    progress.frames as f32 + progress.gaps as f32 * GAP_FITNESS
+}
+
+/// Fill up the population with birds copied off the first one, every bird gets a random net.
+/// The copies take its collider and animation so they look and hit the same
+pub fn start(world: &mut World, size: usize) -> Result<String, String>
+{
+    if size < 2
+    {
+        return Err("a population needs at least 2 birds".to_string());
+    }
+    let errors = stop(world);
+    if !errors.is_empty()
+    {
+        return Err(errors.join("\n"));
+    }
+
+    let first = {
+        let entities = world.entities();
+        let control = world.read_storage::<Controllable>();
+        (&entities, &control).join().map(|(ent, _)| ent).next()
+    };
+    let Some(first) = first else {
+        return Err("there's no bird to copy".to_string());
+    };
+    let collider = world.read_storage::<collider::Collider>().get(first).cloned()
+        .unwrap_or_else(|| collider::Collider::bird(BIRD_SPRITE_SIZE, BIRD_SPRITE_SCALE));
+    let animation = world.read_storage::<Animation>().get(first).cloned();
+
+    let mut birds = vec![first];
+    for _ in 1..size
+    {
+        let mut bird = create_bird(world, collider.clone());
+        if let Some(animation) = &animation
+        {
+            bird = bird.with(animation.clone());
+        }
+        birds.push(bird.build());
+    }
+
+    let mut rng = rand::thread_rng();
+    let members = birds.into_iter().map(|ent| (ent, Net::random(&mut rng))).collect();
+    *world.write_resource::<Population>() = Population { active: true, generation: 1, members, best: 0.0, restart: true };
+    let errors = fly(world);
+    if !errors.is_empty()
+    {
+        return Err(errors.join("\n"));
+    }
+    Ok(format!("training {size} birds"))
+}
+
+/// Back to the one bird the game started with, flown by the player. Returns what went wrong
+pub fn stop(world: &mut World) -> Vec<String>
+{
+    let mut errors = Vec::new();
+    let population = std::mem::take(&mut *world.write_resource::<Population>());
+    // the first member is the game's own bird
+    for (ent, _) in population.members.iter().skip(1)
+    {
+        if let Err(e) = world.delete_entity(*ent)
+        {
+            errors.push(format!("couldn't remove bird {}: {e}", ent.id()));
+        }
+    }
+    if let Some((ent, _)) = population.members.first()
+    {
+        if let Err(e) = world.write_storage::<Pilot>().insert(*ent, Pilot::Player)
+        {
+            errors.push(format!("couldn't hand bird {} back: {e}", ent.id()));
+        }
+    }
+    world.maintain();
+    errors
+}
+
+/// Give every bird its net
+fn fly(world: &mut World) -> Vec<String>
+{
+    let population = world.read_resource::<Population>();
+    let mut pilots = world.write_storage::<Pilot>();
+    let mut errors = Vec::new();
+    for (ent, net) in &population.members
+    {
+        if let Err(e) = pilots.insert(*ent, Pilot::Bot(Box::new(net.clone())))
+        {
+            errors.push(format!("couldn't give bird {} its net: {e}", ent.id()));
+        }
+    }
+    errors
+}
+
+/// Rank the generation that just finished and breed the next one off the best of it
+pub fn next_generation(world: &mut World) -> Vec<String>
+{
+    {
+        let progress = world.read_storage::<Progress>();
+        let mut population = world.write_resource::<Population>();
+        let mut ranked: Vec<(f32, Net)> = population.members.iter()
+            .map(|(ent, net)| (progress.get(*ent).map_or(0.0, fitness), net.clone()))
+            .collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let size = ranked.len();
+        let elite = ((size as f32 * ELITE) as usize).max(1);
+        let parents = ((size as f32 * PARENTS) as usize).max(1);
+        let mut rng = rand::thread_rng();
+        let nets: Vec<Net> = (0..size)
+            .map(|i| if i < elite { ranked[i].1.clone() } else { ranked[rng.gen_range(0, parents)].1.mutated(&mut rng) })
+            .collect();
+
+        population.best = ranked.first().map_or(0.0, |(f, _)| *f);
+        population.generation += 1;
+        for ((_, net), new) in population.members.iter_mut().zip(nets)
+        {
+            *net = new;
+        }
+        population.restart = true;
+    }
+    fly(world)
+}
+
+/// Whether a run has to be started for a new generation, only says yes once per generation
+pub fn take_restart(world: &World) -> bool
+{
+    std::mem::take(&mut world.write_resource::<Population>().restart)
+}
+
+/// Train without a window on a gym env, every generation gets a new course
+pub fn train_headless(generations: u32, size: usize, asset_dir: &Path)
+{
+    let mut env = Env::new(EnvConfig::default());
+    for err in env.load_assets(asset_dir)
+    {
+        eprintln!("{err}");
+    }
+    if let Err(err) = start(env.world_mut(), size)
+    {
+        eprintln!("{err}");
+        return;
+    }
+
+    for generation in 0..generations
+    {
+        env.reset(generation as u64);
+        let mut frames = 0;
+        while !env.world_mut().read_resource::<IsGameover>().0 && frames < MAX_FRAMES
+        {
+            env.tick();
+            frames += 1;
+        }
+
+        let world = env.world_mut();
+        let score = world.read_resource::<Score>().0;
+        let alive = world.read_resource::<Population>().alive(world);
+        for err in next_generation(world)
+        {
+            eprintln!("{err}");
+        }
+        let population = world.read_resource::<Population>();
+        println!("generation {} best fitness {:.0}, score {score}, {alive} made it to the end",
+            population.generation - 1, population.best);
+    }
+}
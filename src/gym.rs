@@ -0,0 +1,299 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use specs::*;
+
+use crate::*;
+use crate::autopilot::{GapView, Observation, PlayerInput};
+use crate::course::{Course, Physics, NOMINAL_DT};
+use crate::obstacles::{ObstaclePair, ObstacleState};
+use crate::patterns::{PatternLibrary, PATTERN_FILE};
+
+/// What an Env observes unless it's told otherwise
+pub const DEFAULT_FEATURES:&str = "bird_y vel gap_dx:0 gap_top:0 gap_bottom:0 gap_dx:1 gap_top:1 gap_bottom:1";
+
+/// Flaps per second the bench policy aims for, about what keeps the bird in the air
+const BENCH_FLAP_RATE:f32 = 2.0;
+
+/// One number of the observation vector. Positions are scaled by the window so they're
+/// about 0 to 1, velocity is in flaps
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feature
+{
+    BirdY,
+    Velocity,
+    /// how far ahead the nth gap is
+    GapDx(usize),
+    GapTop(usize),
+    GapBottom(usize),
+    /// the bird's middle compared to the nth gap's, positive is below it
+    GapDy(usize)
+}
+
+impl Feature
+{
+    /// `bird_y`, `vel`, or `gap_dx`, `gap_top`, `gap_bottom` and `gap_dy` with an optional `:n` for the nth gap ahead
+    pub fn parse(word: &str) -> Result<Feature, String>
+    {
+        let (name, n) = match word.split_once(':')
+        {
+            Some((name, n)) => (name, n.parse::<usize>().map_err(|e| format!("'{n}' isn't a gap number ({e})"))?),
+            None => (word, 0)
+        };
+        match name
+        {
+            "bird_y" => Ok(Feature::BirdY),
+            "vel" => Ok(Feature::Velocity),
+            "gap_dx" => Ok(Feature::GapDx(n)),
+            "gap_top" => Ok(Feature::GapTop(n)),
+            "gap_bottom" => Ok(Feature::GapBottom(n)),
+            "gap_dy" => Ok(Feature::GapDy(n)),
+            _ => Err(format!("unknown feature '{name}', try bird_y, vel, gap_dx, gap_top, gap_bottom or gap_dy"))
+        }
+    }
+
+    /// A whole observation vector, features split by whitespace
+    pub fn parse_all(spec: &str) -> Result<Vec<Feature>, String>
+    {
+        spec.split_whitespace().map(Feature::parse).collect()
+    }
+
+    pub fn value(&self, obs: &Observation) -> f32
+    {
+        // with nothing coming there's just a gap as tall as the screen way off to the right
+        let gap = |n: usize| obs.gaps.get(n).copied().unwrap_or(GapView { x: WINDOW_W, width: 0.0, top: 0.0, bottom: WINDOW_H });
+        match *self
+        {
+            Feature::BirdY => obs.bird.pos_y / WINDOW_H,
+            Feature::Velocity => obs.vel_y / obs.physics.flap,
+            Feature::GapDx(n) => (gap(n).x - obs.bird.pos_x) / WINDOW_W,
+            Feature::GapTop(n) => gap(n).top / WINDOW_H,
+            Feature::GapBottom(n) => gap(n).bottom / WINDOW_H,
+            Feature::GapDy(n) => (obs.bird.pos_y + obs.bird.size_y/2.0 - gap(n).center()) / WINDOW_H
+        }
+    }
+}
+
+/// What a step is worth
+#[derive(Clone, Copy, Debug)]
+pub struct Rewards
+{
+    /// every step the bird lives through
+    pub alive: f32,
+    /// every point scored
+    pub point: f32,
+    /// the step it dies on, instead of the other two
+    pub death: f32
+}
+
+impl Default for Rewards
+{
+    fn default() -> Self
+    {
+        Rewards { alive: 0.01, point: 1.0, death: -1.0 }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EnvConfig
+{
+    pub features: Vec<Feature>,
+    pub rewards: Rewards,
+    /// episodes get cut off here even if the bird's still going
+    pub max_steps: usize
+}
+
+impl Default for EnvConfig
+{
+    fn default() -> Self
+    {
+        EnvConfig {
+            features: Feature::parse_all(DEFAULT_FEATURES).expect("the default features parse"),
+            rewards: Rewards::default(),
+            max_steps: 10_000
+        }
+    }
+}
+
+/// The game without a window, for training bots. Every step is one frame at 60fps and the
+/// action is whether to flap. Same seed, same actions, same episode
+pub struct Env
+{
+    pub config: EnvConfig,
+    world: World,
+    dispatcher: Dispatcher<'static, 'static>,
+    steps: usize,
+    done: bool
+}
+
+impl Env
+{
+    pub fn new(config: EnvConfig) -> Env
+    {
+        let mut dispatcher = build_dispatcher();
+        let mut world = create_world(&mut dispatcher);
+        // no sprite, so the collider goes by its shape alone
+        create_bird(&mut world, collider::Collider::bird(BIRD_SPRITE_SIZE, BIRD_SPRITE_SCALE)).build();
+        create_bounds(&mut world);
+        world.insert(Delta(Duration::from_secs_f32(NOMINAL_DT)));
+
+        Env { config, world, dispatcher, steps: 0, done: true }
+    }
+
+    /// Tuning and patterns out of an asset dir, without them it's the built in defaults
+    pub fn load_assets(&mut self, dir: &Path) -> Vec<String>
+    {
+        let read = |file: &str| std::fs::read_to_string(dir.join(file.trim_start_matches('/')));
+        let mut errors = Vec::new();
+        if let Ok(contents) = read(tuning::TUNING_FILE)
+        {
+            errors.extend(tuning::apply(&mut self.world, &contents));
+        }
+        if let Ok(contents) = read(PATTERN_FILE)
+        {
+            let (library, errs) = PatternLibrary::parse(&contents);
+            self.world.insert(library);
+            errors.extend(errs);
+        }
+        errors
+    }
+
+    /// Start a new episode, the seed decides the whole course
+    pub fn reset(&mut self, seed: u64) -> Vec<f32>
+    {
+        reset_world(&mut self.world);
+        self.world.write_resource::<Course>().reseed(seed);
+        *self.world.write_resource::<PlayerInput>() = PlayerInput::default();
+        self.world.maintain();
+        self.steps = 0;
+        self.done = false;
+        self.observe()
+    }
+
+    /// One frame, returns what the bird sees after it, the reward and whether the episode's over.
+    /// Once it's over nothing moves until the next reset
+    pub fn step(&mut self, flap: bool) -> (Vec<f32>, f32, bool)
+    {
+        if self.done
+        {
+            return (self.observe(), 0.0, true);
+        }
+
+        let score = self.world.read_resource::<Score>().0;
+        self.world.write_resource::<PlayerInput>().flap = flap;
+        self.tick();
+        self.steps += 1;
+
+        let rewards = self.config.rewards;
+        let points = self.world.read_resource::<Score>().0.saturating_sub(score);
+        let dead = self.world.read_resource::<IsGameover>().0;
+        let reward = if dead { rewards.death } else { rewards.alive + rewards.point * points as f32 };
+        self.done = dead || self.steps >= self.config.max_steps;
+        (self.observe(), reward, self.done)
+    }
+
+    /// One frame of the simulation, for whoever drives the birds some other way than step
+    pub fn tick(&mut self)
+    {
+        self.dispatcher.dispatch_seq(&self.world);
+        self.world.maintain();
+        // no console without a window, and stdout might be a bot's
+        for err in std::mem::take(&mut self.world.write_resource::<SystemErrors>().0)
+        {
+            eprintln!("{err}");
+        }
+    }
+
+    pub fn world_mut(&mut self) -> &mut World
+    {
+        &mut self.world
+    }
+
+    pub fn observation_size(&self) -> usize
+    {
+        self.config.features.len()
+    }
+
+    pub fn score(&self) -> u64
+    {
+        self.world.read_resource::<Score>().0
+    }
+
+    fn observe(&self) -> Vec<f32>
+    {
+        let rect = self.world.read_storage::<Rect>();
+        let velo = self.world.read_storage::<Velocity>();
+        let control = self.world.read_storage::<Controllable>();
+        let pairs = self.world.read_storage::<ObstaclePair>();
+        let states = self.world.read_storage::<ObstacleState>();
+
+        let Some((r, vel, _)) = (&rect, &velo, &control).join().next() else {
+            return vec![0.0; self.observation_size()];
+        };
+        let obs = Observation::new(r, vel.y, (&pairs, &states).join(), Physics::from_world(&self.world));
+        self.config.features.iter().map(|f| f.value(&obs)).collect()
+    }
+}
+
+/// Run episodes flat out with random flapping on an Env per core and print how fast they went
+pub fn bench(episodes: u64, asset_dir: &Path)
+{
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get() as u64);
+    let start = Instant::now();
+    let runs: Vec<std::thread::JoinHandle<(u64, f32, u64)>> = (0..threads)
+        .map(|thread| {
+            let asset_dir = asset_dir.to_path_buf();
+            std::thread::spawn(move || {
+                let mut env = Env::new(EnvConfig::default());
+                let errors = env.load_assets(&asset_dir);
+                if thread == 0
+                {
+                    for err in errors
+                    {
+                        eprintln!("{err}");
+                    }
+                }
+
+                let mut rng = rand::thread_rng();
+                let (mut steps, mut reward, mut score) = (0, 0.0, 0);
+                // every thread takes every nth seed
+                for seed in (thread..episodes).step_by(threads as usize)
+                {
+                    env.reset(seed);
+                    loop
+                    {
+                        let (_, r, done) = env.step(rng.gen::<f32>() < BENCH_FLAP_RATE * NOMINAL_DT);
+                        steps += 1;
+                        reward += r;
+                        if done
+                        {
+                            break;
+                        }
+                    }
+                    score += env.score();
+                }
+                (steps, reward, score)
+            })
+        })
+        .collect();
+
+    let (mut steps, mut reward, mut score) = (0, 0.0, 0);
+    for run in runs
+    {
+        match run.join()
+        {
+            Ok((s, r, sc)) => {
+                steps += s;
+                reward += r;
+                score += sc;
+            },
+            Err(_) => eprintln!("a bench thread panicked")
+        }
+    }
+    let secs = start.elapsed().as_secs_f32();
+
+    println!("{episodes} episodes, {steps} steps in {secs:.2}s on {threads} threads");
+    println!("{:.0} episodes/s, {:.0} steps/s", episodes as f32 / secs, steps as f32 / secs);
+    println!("mean reward {:.3}, mean score {:.2}", reward / episodes.max(1) as f32, score as f32 / episodes.max(1) as f32);
+}
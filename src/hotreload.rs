@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// How often we go and look at the files, checking every frame is a waste
+const POLL_INTERVAL:Duration = Duration::from_millis(500);
+
+/// Watches asset files by polling their modification times
+/// Paths are the same "/file.ext" names we hand to the ggez filesystem
+pub struct HotReload
+{
+    asset_dir: PathBuf,
+    modified: HashMap<String, Option<SystemTime>>,
+    since_poll: Duration,
+}
+
+impl HotReload
+{
+    pub fn new(asset_dir: PathBuf, files: &[&str]) -> HotReload
+    {
+        let mut hot_reload = HotReload {
+            asset_dir,
+            modified: HashMap::new(),
+            since_poll: Duration::ZERO,
+        };
+        for file in files
+        {
+            hot_reload.watch(file);
+        }
+        hot_reload
+    }
+
+    /// Start watching another file, files that are already watched are left alone
+    pub fn watch(&mut self, file: &str)
+    {
+        if !self.modified.contains_key(file)
+        {
+            let modified = self.modified_time(file);
+            self.modified.insert(file.to_string(), modified);
+        }
+    }
+
+    fn modified_time(&self, file: &str) -> Option<SystemTime>
+    {
+        let path = self.asset_dir.join(file.trim_start_matches('/'));
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+
+    /// Returns the files that changed since the last poll, empty until POLL_INTERVAL has passed
+    pub fn poll(&mut self, delta: Duration) -> Vec<String>
+    {
+        self.since_poll += delta;
+        if self.since_poll < POLL_INTERVAL
+        {
+            return Vec::new();
+        }
+        self.since_poll = Duration::ZERO;
+
+        let mut changed = Vec::new();
+        let files: Vec<String> = self.modified.keys().cloned().collect();
+        for file in files
+        {
+            let modified = self.modified_time(&file);
+            // a file that is mid-save can briefly be missing, wait for it to come back
+            if modified.is_some() && modified != self.modified[&file]
+            {
+                self.modified.insert(file.clone(), modified);
+                changed.push(file);
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,439 @@
+use std::io::Read;
+
+use ggez::{filesystem, Context};
+use specs::*;
+
+use crate::*;
+use crate::behaviours::Motion;
+use crate::course::Gap;
+use crate::patterns::MIN_SPACING;
+
+/// Every `.lvl` file in here shows up in the level select, sorted by file name
+pub const LEVEL_DIR:&str = "/levels";
+
+/// A pipe pair at a fixed distance, distances are in pixels the bird travels from the start
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LevelPipe
+{
+    pub x: f32,
+    pub gap: Gap
+}
+
+/// Changes a tuning value once the bird gets to x
+#[derive(Clone, Debug, PartialEq)]
+pub struct LevelEvent
+{
+    pub x: f32,
+    pub var: String,
+    pub value: f32
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Level
+{
+    pub name: String,
+    pub file: String,
+    /// tuning values set when the level starts
+    pub tuning: Vec<(String, f32)>,
+    pub pipes: Vec<LevelPipe>,
+    pub events: Vec<LevelEvent>,
+    /// the level is done once the bird has come this far
+    pub finish: f32
+}
+
+impl Level
+{
+    /// An empty level, named after its file
+    pub fn new(file: &str) -> Level
+    {
+        let stem = file.rsplit('/').next().unwrap_or(file).trim_end_matches(".lvl");
+        Level {
+            name: stem.to_string(),
+            file: file.to_string(),
+            tuning: Vec::new(),
+            pipes: Vec::new(),
+            events: Vec::new(),
+            finish: 0.0
+        }
+    }
+
+    /// Parse a level file, bad lines are skipped and reported back
+    pub fn parse(file: &str, contents: &str) -> (Level, Vec<String>)
+    {
+        let mut level = Level::new(file);
+        let mut errors = Vec::new();
+
+        for (n, line) in contents.lines().enumerate()
+        {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty()
+            {
+                continue;
+            }
+
+            let result = match line.split_once('=')
+            {
+                Some((key, value)) => level.parse_header(key.trim(), value.trim()),
+                None => level.parse_entry(line)
+            };
+            if let Err(err) = result
+            {
+                errors.push(format!("{file}:{} {err}", n + 1));
+            }
+        }
+
+        level.sort();
+
+        // the pool only has enough pairs for the tightest pattern spacing
+        for pair in level.pipes.windows(2)
+        {
+            if pair[1].x - pair[0].x < OBSTACLE_SPACING * MIN_SPACING
+            {
+                errors.push(format!("{file} pipes at {} and {} are closer than {}, the second one will come in late",
+                    pair[0].x, pair[1].x, OBSTACLE_SPACING * MIN_SPACING));
+            }
+        }
+
+        if level.finish <= 0.0
+        {
+            // no finish given, end it a screen after the last pipe
+            level.finish = level.pipes.last().map_or(0.0, |p| p.x) + WINDOW_W;
+        }
+        (level, errors)
+    }
+
+    /// Put pipes and events in the order they come up, LevelRun relies on it
+    pub fn sort(&mut self)
+    {
+        self.pipes.sort_by(|a, b| a.x.total_cmp(&b.x));
+        self.events.sort_by(|a, b| a.x.total_cmp(&b.x));
+
+        let mut last_x = 0.0;
+        for pipe in &mut self.pipes
+        {
+            pipe.gap.spacing = pipe.x - last_x;
+            last_x = pipe.x;
+        }
+    }
+
+    /// Write the level back out in the format parse() reads, comments don't survive
+    pub fn to_file_string(&self) -> String
+    {
+        let mut out = vec![
+            "# see 01_first_flight.lvl for the format".to_string(),
+            String::new(),
+            format!("name = {}", self.name)
+        ];
+        out.extend(self.tuning.iter().map(|(var, value)| format!("{var} = {value}")));
+        out.push(String::new());
+
+        for pipe in &self.pipes
+        {
+            let mut line = format!("pipe {} {} {}", pipe.x, pipe.gap.center, pipe.gap.size);
+            if pipe.gap.motion != Motion::Still
+            {
+                line.push_str(&format!(" {}", pipe.gap.motion));
+            }
+            out.push(line);
+        }
+        if !self.events.is_empty()
+        {
+            out.push(String::new());
+        }
+        out.extend(self.events.iter().map(|e| format!("event {} {} {}", e.x, e.var, e.value)));
+
+        out.push(String::new());
+        out.push(format!("finish = {}", self.finish));
+        out.push(String::new());
+        out.join("\n")
+    }
+
+    fn parse_header(&mut self, key: &str, value: &str) -> Result<(), String>
+    {
+        match key
+        {
+            "name" => self.name = value.to_string(),
+            "finish" => self.finish = parse_num(value, "distance")?,
+            var if tuning::TUNING_VARS.contains(&var) => self.tuning.push((var.to_string(), parse_num(value, var)?)),
+            _ => return Err(format!("unknown key '{key}'"))
+        }
+        Ok(())
+    }
+
+    fn parse_entry(&mut self, line: &str) -> Result<(), String>
+    {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice()
+        {
+            ["pipe", x, center, rest @ ..] => {
+                // the gap size is optional, anything after it is the motion
+                let (size, motion) = match rest.split_first()
+                {
+                    Some((size, motion)) if size.parse::<f32>().is_ok() => (parse_num(size, "gap size")?, motion),
+                    _ => (OBSTACLE_TIGTHFACTOR, rest)
+                };
+                let motion = match motion
+                {
+                    [] => Motion::Still,
+                    _ => Motion::parse(&motion.join(" "))?
+                };
+                self.pipes.push(LevelPipe {
+                    x: parse_num(x, "distance")?,
+                    gap: Gap {
+                        center: parse_num(center, "gap center")?,
+                        size,
+                        spacing: 0.0,
+                        motion
+                    }
+                });
+            },
+            ["event", x, var, value] => {
+                if !tuning::TUNING_VARS.contains(var)
+                {
+                    return Err(format!("unknown tuning value '{var}'"));
+                }
+                self.events.push(LevelEvent {
+                    x: parse_num(x, "distance")?,
+                    var: var.to_string(),
+                    value: parse_num(value, var)?
+                });
+            },
+            _ => return Err(format!("expected name = value, pipe or event, got '{line}'"))
+        }
+        Ok(())
+    }
+}
+
+fn parse_num(value: &str, name: &str) -> Result<f32, String>
+{
+    value.parse::<f32>().map_err(|e| format!("'{value}' isn't a valid {name} ({e})"))
+}
+
+/// Every level found in LEVEL_DIR plus which one the menu has selected, 0 is endless mode
+#[derive(Clone, Debug, Default)]
+pub struct LevelList
+{
+    pub levels: Vec<Level>,
+    pub selected: usize
+}
+
+impl LevelList
+{
+    pub fn selected_level(&self) -> Option<&Level>
+    {
+        self.selected.checked_sub(1).and_then(|i| self.levels.get(i))
+    }
+
+    pub fn selected_name(&self) -> &str
+    {
+        self.selected_level().map_or("ENDLESS", |l| l.name.as_str())
+    }
+
+    pub fn select_next(&mut self)
+    {
+        self.selected = (self.selected + 1) % (self.levels.len() + 1);
+    }
+
+    pub fn select_prev(&mut self)
+    {
+        self.selected = self.selected.checked_sub(1).unwrap_or(self.levels.len());
+    }
+
+    /// Select by level name or file name, case doesn't matter
+    pub fn select(&mut self, name: &str) -> bool
+    {
+        if name.eq_ignore_ascii_case("endless")
+        {
+            self.selected = 0;
+            return true;
+        }
+        match self.levels.iter().position(|l| l.name.eq_ignore_ascii_case(name) || l.file.ends_with(name))
+        {
+            Some(i) => {
+                self.selected = i + 1;
+                true
+            },
+            None => false
+        }
+    }
+}
+
+/// (Re)load every level through the ggez filesystem, keeps the selection if that level's still there
+pub fn load(ctx: &mut Context, world: &mut World) -> Vec<String>
+{
+    let mut errors = Vec::new();
+    let mut files: Vec<_> = match filesystem::read_dir(ctx, LEVEL_DIR)
+    {
+        Ok(files) => files.filter(|f| f.extension().is_some_and(|e| e == "lvl")).collect(),
+        Err(_) => Vec::new()
+    };
+    files.sort();
+
+    let mut levels = Vec::new();
+    for path in files
+    {
+        let file = path.to_string_lossy().replace('\\', "/");
+        let mut contents = String::new();
+        let read = filesystem::open(ctx, &path).map_err(|e| e.to_string())
+            .and_then(|mut f| f.read_to_string(&mut contents).map_err(|e| e.to_string()));
+        if let Err(e) = read
+        {
+            errors.push(format!("couldn't read {file}: {e}"));
+            continue;
+        }
+
+        let (level, level_errors) = Level::parse(&file, &contents);
+        errors.extend(level_errors);
+        levels.push(level);
+    }
+
+    let mut list = LevelList { levels, selected: 0 };
+    if let Some(old) = world.try_fetch::<LevelList>()
+    {
+        if let Some(level) = old.selected_level()
+        {
+            list.select(&level.file);
+        }
+    }
+    world.insert(list);
+    errors
+}
+
+/// Progress through the level being played, no level means endless mode
+#[derive(Clone, Debug, Default)]
+pub struct LevelRun
+{
+    pub level: Option<Level>,
+    pub traveled: f32,
+    pub complete: bool,
+    next_pipe: usize,
+    next_event: usize,
+    /// tuning events that are due, the game applies them after the tick
+    pending: Vec<(String, f32)>
+}
+
+impl LevelRun
+{
+    /// Start partway into a level, pipes already behind the bird are skipped
+    /// and events that would've happened by then are queued right away
+    pub fn starting_at(level: Level, traveled: f32) -> LevelRun
+    {
+        let next_pipe = level.pipes.iter().take_while(|p| BIRD_X + p.x - traveled < 0.0).count();
+        let mut run = LevelRun {
+            level: Some(level),
+            next_pipe,
+            ..LevelRun::default()
+        };
+        run.advance(traveled);
+        run
+    }
+
+    /// Screen x of the finish line
+    pub fn finish_x(&self) -> Option<f32>
+    {
+        self.level.as_ref().map(|l| BIRD_X + l.finish - self.traveled)
+    }
+
+    /// The next pipe if it's close enough to come on screen
+    pub fn due_pipe(&self) -> Option<LevelPipe>
+    {
+        let pipe = *self.level.as_ref()?.pipes.get(self.next_pipe)?;
+        (BIRD_X + pipe.x - self.traveled <= SPAWN_X).then_some(pipe)
+    }
+
+    pub fn pipe_spawned(&mut self)
+    {
+        self.next_pipe += 1;
+    }
+
+    /// Move the level along, queues up events and checks for the finish
+    pub fn advance(&mut self, distance: f32)
+    {
+        let Some(level) = &self.level else {
+            return;
+        };
+        self.traveled += distance;
+
+        while let Some(event) = level.events.get(self.next_event).filter(|e| e.x <= self.traveled)
+        {
+            self.pending.push((event.var.clone(), event.value));
+            self.next_event += 1;
+        }
+        if self.traveled >= level.finish
+        {
+            self.complete = true;
+        }
+    }
+
+    pub fn take_events(&mut self) -> Vec<(String, f32)>
+    {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn pipe(x: f32, center: f32, size: f32, motion: Motion) -> LevelPipe
+    {
+        LevelPipe { x, gap: Gap { center, size, spacing: 0.0, motion } }
+    }
+
+    #[test]
+    fn round_trip()
+    {
+        let mut level = Level::new("/levels/test.lvl");
+        level.name = "Round trip".to_string();
+        level.tuning = vec![("gravity".to_string(), 0.45), ("speed".to_string(), -3.25)];
+        level.pipes = vec![
+            pipe(OBSTACLE_SPACING, 300.0, 220.0, Motion::Still),
+            pipe(OBSTACLE_SPACING*2.0, 412.5, 180.0, Motion::Oscillate { amplitude: 40.0, period: 2.5 }),
+            pipe(OBSTACLE_SPACING*3.0, 250.0, 260.0, Motion::Breathe { min_size: 120.0, period: 3.0 }),
+            pipe(OBSTACLE_SPACING*4.5, 333.3, 200.0, Motion::Drop)
+        ];
+        level.events = vec![LevelEvent { x: OBSTACLE_SPACING*2.5, var: "speed".to_string(), value: -4.1 }];
+        level.finish = OBSTACLE_SPACING*6.0;
+        level.sort();
+
+        let (parsed, errors) = Level::parse("/levels/test.lvl", &level.to_file_string());
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(parsed, level);
+    }
+
+    #[test]
+    fn hand_written_round_trip()
+    {
+        // out of order, a default gap size and comments, which saving drops
+        let file = format!("# a comment\n\
+            name = Hand written\n\
+            flap = -9 # trailing comment\n\
+            pipe {b} 350 drop\n\
+            pipe {a} 300 240\n\
+            event {a} gravity 0.6\n",
+            a = OBSTACLE_SPACING, b = OBSTACLE_SPACING*2.0);
+        let (level, errors) = Level::parse("/levels/hand.lvl", &file);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(level.name, "Hand written");
+        assert_eq!(level.pipes[0].x, OBSTACLE_SPACING);
+        assert_eq!(level.pipes[1].gap.size, OBSTACLE_TIGTHFACTOR);
+        assert_eq!(level.pipes[1].gap.spacing, OBSTACLE_SPACING);
+        assert_eq!(level.finish, OBSTACLE_SPACING*2.0 + WINDOW_W);
+
+        let saved = level.to_file_string();
+        assert!(!saved.contains("comment"));
+        assert_eq!(Level::parse("/levels/hand.lvl", &saved).0, level);
+    }
+
+    #[test]
+    fn bad_lines()
+    {
+        let file = "name = Bad\nwidth = 3\npipe 500 x\nevent 600 wind 2\nwobble\npipe 500 300\n";
+        let (level, errors) = Level::parse("/levels/bad.lvl", file);
+        assert_eq!(level.pipes.len(), 1);
+        assert_eq!(errors.len(), 4, "{errors:?}");
+        assert!(errors[0].starts_with("/levels/bad.lvl:2 "));
+        assert!(errors[3].starts_with("/levels/bad.lvl:5 "));
+    }
+}
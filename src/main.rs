@@ -1,12 +1,33 @@
+// match is used for input handling even with one arm so more keys/buttons slot in easily
+#![allow(clippy::single_match)]
+
 mod animation;
+mod autopilot;
+mod behaviours;
+mod collider;
+mod console;
+mod course;
+mod debug;
+mod editor;
+mod evolution;
+mod gym;
+mod hotreload;
+mod levels;
+mod mixer;
+mod obstacles;
+mod patterns;
+mod remote;
+mod scoring;
+mod systems;
+mod triggers;
+mod tuning;
 
 use std::time::Duration;
+use std::f32::consts::FRAC_PI_2;
 use std::{path};
 
-use ggez::audio::SoundSource;
 use ggez::graphics::spritebatch::SpriteBatch;
 use glam::*;
-use rand::*;
 
 use ggez::*;
 use ggez::conf::{WindowSetup, WindowMode, FullscreenType};
@@ -14,22 +35,24 @@ use ggez::event::MouseButton;
 use ggez::graphics::*;
 use specs::*;
 
-type Point2 = Vec2;
-
-// DEBUGGING 
-const SHOW_HITBOXES:bool = false;
-const RUN_SYS_MOVEMENT:bool = true;
-const RUN_SYS_COLLISION:bool = true;
-const RUN_SYS_OBSTACLES:bool = true;
-
+use debug::{DebugSettings, TimeScale};
+use obstacles::*;
+use systems::*;
 
-const OBST_AMOUNT:u8 = 3;
+type Point2 = Vec2;
 
 const WINDOW_H:f32 = 1100.0;
 const WINDOW_W:f32 = 1000.0;
 
 const SQUARE_SIZE:f32 = 65.0;
 
+const BIRD_X:f32 = WINDOW_W/3.0 - SQUARE_SIZE;
+const BIRD_Y:f32 = WINDOW_H/3.0 - SQUARE_SIZE;
+
+/// Size of one frame of the bird sheet and how much it's scaled up on screen
+const BIRD_SPRITE_SIZE:(f32, f32) = (32.0, 25.0);
+const BIRD_SPRITE_SCALE:f32 = 3.0;
+
 const OBSTACLE_TIGTHFACTOR:f32 = 220.0;
 
 const RNG_LOW:f32 = 0.2;
@@ -39,6 +62,16 @@ const OBST_SPEED:f32 = -3.5;
 const BIRD_FLAP:f32 = 3.5;
 const GRAVITY:f32 = 10.0;
 
+const DIFFICULTY_STEP:u64 = 10;
+
+/// Seconds the menu sits untouched before the demo starts
+const ATTRACT_DELAY:f32 = 8.0;
+/// Strategy that flies the demo
+const ATTRACT_PILOT:&str = "planner";
+
+const PLAYER_SHEET:&str = "/bird.png";
+const OBSTACLE_SHEET:&str = "/obst.png";
+
 #[derive(Clone, Copy, Default)]
 struct Delta(Duration);
 
@@ -46,9 +79,19 @@ struct Delta(Duration);
 struct Gravity(f32);
 
 #[derive(Clone, Copy, Default)]
-struct Score(u8);
+struct FlapStrength(f32);
 
-#[derive(Component, Debug)]
+#[derive(Clone, Copy, Default)]
+struct ObstacleSpeed(f32);
+
+#[derive(Clone, Copy, Default)]
+struct Score(u64);
+
+/// Goes up a level every DIFFICULTY_STEP points, unlocks harder obstacle patterns
+#[derive(Clone, Copy, Default)]
+struct Difficulty(u8);
+
+#[derive(Component, Clone, Copy, Debug)]
 #[storage(VecStorage)]
 struct Rect
 {
@@ -66,6 +109,61 @@ impl PartialEq for Rect {
     }
 }
 impl Eq for Rect {}
+impl Rect
+{
+    /// Somewhere between two rects, t of 0 is self and 1 is other
+    fn lerp(&self, other: &Rect, t: f32) -> Rect
+    {
+        Rect {
+            pos_x: self.pos_x + (other.pos_x - self.pos_x) * t,
+            pos_y: self.pos_y + (other.pos_y - self.pos_y) * t,
+            size_x: self.size_x + (other.size_x - self.size_x) * t,
+            size_y: self.size_y + (other.size_y - self.size_y) * t
+        }
+    }
+
+    /// Smallest rect around both
+    fn union(&self, other: &Rect) -> Rect
+    {
+        let (x, y) = (self.pos_x.min(other.pos_x), self.pos_y.min(other.pos_y));
+        Rect {
+            pos_x: x,
+            pos_y: y,
+            size_x: (self.pos_x + self.size_x).max(other.pos_x + other.size_x) - x,
+            size_y: (self.pos_y + self.size_y).max(other.pos_y + other.size_y) - y
+        }
+    }
+
+    fn overlaps(&self, other: &Rect) -> bool
+    {
+        self.pos_x <= other.pos_x + other.size_x
+        && other.pos_x <= self.pos_x + self.size_x
+        && self.pos_y <= other.pos_y + other.size_y
+        && other.pos_y <= self.pos_y + self.size_y
+    }
+}
+
+/// Where a Rect was at the start of the tick, collisions sweep from there to where it is now
+#[derive(Component, Clone, Copy, Debug)]
+#[storage(VecStorage)]
+struct LastRect(Rect);
+
+/// Tells the ceiling and floor apart from everything else that collides
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+#[storage(VecStorage)]
+enum Boundary
+{
+    Ceiling,
+    Floor
+}
+
+/// Hits this run, kept by CollisionStatsSystem off the collision events
+#[derive(Clone, Copy, Debug, Default)]
+struct CollisionStats
+{
+    hits: u32,
+    last: Option<collider::CollisionEvent>
+}
 
 #[derive(Component, Debug)]
 #[storage(VecStorage)]
@@ -75,168 +173,397 @@ struct Velocity
     y: f32
 }
 
-#[derive(Component, Debug)]
+#[derive(Component, Clone, Debug)]
 #[storage(VecStorage)]
 struct Animation
 {
     spritesheet: animation::Spritesheet,
 }
 
-#[derive(Component, Debug)]
-#[storage(VecStorage)]
-struct Sound
-{
-    sounds: Vec<ggez::audio::Source>,
-}
-
 #[derive(Component,Default)]
 #[storage(NullStorage)]
 struct Controllable;
 
+/// A bird that's hit something, it stays where it hit and sits out the rest of the run
 #[derive(Component,Default)]
 #[storage(NullStorage)]
-struct Dirty;
+struct Dead;
+
+/// How far a bird got this run
+#[derive(Component, Clone, Copy, Debug, Default)]
+#[storage(VecStorage)]
+struct Progress
+{
+    /// ticks alive
+    frames: u32,
+    /// gaps gone into, whether or not the bird was first there and got the point
+    gaps: u32
+}
 
 
 #[derive(Component,Default)]
 #[storage(NullStorage)]
 struct Collision;
 
-#[derive(Component,Default)]
-#[storage(VecStorage)]
-struct Obstacle(u8);
-
 #[derive(Component,Default)]
 #[storage(VecStorage)]
 struct IsGameover(bool);
 
+/// Whatever went wrong inside a system this tick, update() hands it to the console
+#[derive(Default)]
+struct SystemErrors(Vec<String>);
+
 enum State
 {
     Menu,
     Playing,
-    GameOver
+    GameOver,
+    LevelComplete,
+    Editor
 }
 
 struct GameState
 {
     state: State,
-    difficulty: u8, // TODO increase with score? i guess
 
     obst_sheet: graphics::Image,
+    font: graphics::Font,
+
+    console: console::Console,
+    hot_reload: hotreload::HotReload,
+    /// tuning from before the current level changed it
+    tuning_backup: Vec<(&'static str, f32)>,
+    /// kept around while play testing so we can go back to it
+    editor: Option<editor::Editor>,
+    asset_dir: path::PathBuf,
+    /// a bot's playing endless on its own until someone touches something
+    attract: bool,
+    /// seconds since anyone did anything in the menu
+    menu_idle: f32,
+    /// an outside bot flying the bird, see remote.rs
+    remote: Option<remote::Link>,
 
     ecs: World,
-    movement_sys: MovementSystem,
-    obstacle_sys: ObstacleSysten,
-    collision_sys: CollisionSystem,
-    score_sys: ScoreSystem
+    dispatcher: Dispatcher<'static, 'static>
 }
 impl GameState
 {
-    fn new(ctx: &mut Context, player_spritesheet: animation::Spritesheet) -> GameResult<GameState>
+    fn new(ctx: &mut Context, player_spritesheet: animation::Spritesheet, asset_dir: path::PathBuf) -> GameResult<GameState>
     {
-        let mut world = World::new();
+        let mut dispatcher = build_dispatcher();
+        let mut world = create_world(&mut dispatcher);
 
-        world.insert(Delta(Duration::from_nanos(0)));
-        world.insert(Gravity(GRAVITY));
-        world.insert(Score(0));
-        world.insert(IsGameover(false));
-        
-        world.register::<Rect>();
-        world.register::<Dirty>();
-        world.register::<Sound>();
-        world.register::<Obstacle>();
-        world.register::<Velocity>();
-        world.register::<Animation>();
-        world.register::<Collision>();
-        world.register::<Controllable>();
-        
-        world
-            .create_entity()
-            .with(Rect{ pos_x: WINDOW_W/3.0 - SQUARE_SIZE, pos_y: WINDOW_H/3.0 - SQUARE_SIZE, 
-                size_x: SQUARE_SIZE, size_y: SQUARE_SIZE})
-            .with(Velocity { x: 0.0, y: 0.0 })
-            .with(Collision)
-            .with(Controllable)
+        let mut bird_collider = collider::Collider::bird(player_spritesheet.sprite_size, player_spritesheet.img_scale);
+        bird_collider.mask = Some(collider::AlphaMask::from_image(ctx, &player_spritesheet.sheet)?);
+        create_bird(&mut world, bird_collider)
             .with(Animation {
                 spritesheet: player_spritesheet
             })
-            .with(Sound {
-                sounds: vec![
-                    audio::Source::new(ctx, "/flap1.mp3").unwrap(),
-                    audio::Source::new(ctx, "/flap2.mp3").unwrap()
-                ]
-            })
             .build();
+        create_bounds(&mut world);
         
-        world
-            .create_entity()
-            .with(Rect{ pos_x: -1.0, pos_y: -1.0,
-                size_x: WINDOW_W + 1.0, size_y: SQUARE_SIZE/2.0})
-            .with(Collision)
-            .build();
-            
-        world
-            .create_entity()
-            .with(Rect{ pos_x: -1.0, pos_y: WINDOW_H - (SQUARE_SIZE/2.0),
-                size_x: WINDOW_W + 1.0, size_y: SQUARE_SIZE/2.0})
-            .with(Collision)
-            .build();
+        let mut console = console::Console::default();
+        for err in tuning::load(ctx, &mut world)
+        {
+            console.print(&err);
+        }
+        for err in patterns::load(ctx, &mut world)
+        {
+            console.print(&err);
+        }
+        for err in levels::load(ctx, &mut world)
+        {
+            console.print(&err);
+        }
+        for err in mixer::load(ctx, &mut world)
+        {
+            console.print(&err);
+        }
+        for err in autopilot::load_policy(ctx, &mut world)
+        {
+            console.print(&err);
+        }
+
+        let watched = vec![PLAYER_SHEET, OBSTACLE_SHEET, tuning::TUNING_FILE, patterns::PATTERN_FILE, mixer::AUDIO_FILE];
+        let mut hot_reload = hotreload::HotReload::new(asset_dir.clone(), &watched);
+        for clip in world.read_resource::<mixer::AudioManager>().clip_files()
+        {
+            hot_reload.watch(clip);
+        }
 
-        create_obstacles(&mut world, OBST_AMOUNT);
-        
-        
         let gs = GameState {
             state: State::Menu,
-            difficulty: 0,
-            obst_sheet: graphics::Image::new(ctx, "/obst.png").unwrap(),
+            obst_sheet: graphics::Image::new(ctx, OBSTACLE_SHEET).unwrap(),
+            font: graphics::Font::new(ctx, "/font.ttf")?,
+            console,
+            hot_reload,
+            tuning_backup: Vec::new(),
+            editor: None,
+            asset_dir,
+            attract: false,
+            menu_idle: 0.0,
+            remote: None,
             ecs: world,
-            movement_sys: MovementSystem,
-            obstacle_sys: ObstacleSysten,
-            collision_sys: CollisionSystem,
-            score_sys: ScoreSystem
+            dispatcher
         };
         Ok(gs)
     }
 
+    /// Start over with whatever level the menu has selected
     fn reset_game(&mut self)
     {
-        let mut rect  = self.ecs.write_storage::<Rect>();
-        let mut dirty = self.ecs.write_storage::<Dirty>();
-        let obst = self.ecs.read_storage::<Obstacle>();
-        let mut velo  = self.ecs.write_storage::<Velocity>();
-        let controllable  = self.ecs.write_storage::<Controllable>();
+        let level = self.ecs.read_resource::<levels::LevelList>().selected_level().cloned();
+        self.start_run(level, 0.0);
+    }
 
-        let entities = self.ecs.entities();
+    /// Start a run of a level from some distance in, or endless mode without a level
+    fn start_run(&mut self, level: Option<levels::Level>, from: f32)
+    {
+        reset_world(&mut self.ecs);
 
-        for (r, vel, _) in (&mut rect, &mut velo, &controllable).join()
+        // a level starts from its own tuning, endless from whatever was there before
+        self.restore_tuning();
+        if let Some(level) = &level
         {
-            *r = Rect{ pos_x: WINDOW_W/3.0 - SQUARE_SIZE, pos_y: WINDOW_H/3.0 - SQUARE_SIZE, size_x: SQUARE_SIZE, size_y: SQUARE_SIZE};
-            *vel = Velocity { x: 0.0, y: 0.0 };
+            self.tuning_backup = tuning::TUNING_VARS.iter()
+                .filter_map(|var| tuning::get(&self.ecs, var).map(|value| (*var, value)))
+                .collect();
+            for (var, value) in &level.tuning
+            {
+                if let Err(err) = tuning::set(&mut self.ecs, var, *value)
+                {
+                    self.console.print(&format!("{}: {err}", level.file));
+                }
+            }
         }
-        
-        for (ent, r, _) in (&entities, &mut rect, &obst).join()
+        *self.ecs.write_resource::<levels::LevelRun>() = match level
         {
-            // we're keeping the same height cause im lazy fuck you
-            *r = Rect{ pos_x: WINDOW_W+50.0, pos_y: r.pos_y, size_x: r.size_x, size_y: r.size_y};
-            
-            if let None = dirty.remove(ent)
+            Some(level) => levels::LevelRun::starting_at(level, from),
+            None => levels::LevelRun::default()
+        };
+
+        self.state = State::Playing;
+    }
+
+    /// Let the demo bot play endless, whatever level the menu has selected
+    fn start_attract(&mut self)
+    {
+        if let Err(err) = autopilot::set_pilot(&mut self.ecs, ATTRACT_PILOT)
+        {
+            self.console.print(&err);
+            return;
+        }
+        self.start_run(None, 0.0);
+        self.attract = true;
+    }
+
+    /// Take the bird back off the demo bot and go back to the menu
+    fn stop_attract(&mut self, ctx: &mut Context)
+    {
+        self.attract = false;
+        self.menu_idle = 0.0;
+        if let Err(err) = autopilot::set_pilot(&mut self.ecs, "off")
+        {
+            self.console.print(&err);
+        }
+        self.back_to_menu(ctx);
+    }
+
+    /// Take in what the bot's sent. In lockstep this waits a little for it to decide the coming tick,
+    /// or to reset when there's no run going, and says to hold off if it hasn't
+    fn drive_remote(&mut self, link: &mut remote::Link, should_tick: bool) -> remote::Turn
+    {
+        loop
+        {
+            let playing = matches!(self.state, State::Playing);
+            let lockstep = link.mode == remote::Mode::Lockstep;
+            let wait = if lockstep && (should_tick || !playing) { remote::LOCKSTEP_WAIT } else { Duration::ZERO };
+            match link.receive(wait)
             {
-                // nothing happens...
+                remote::Incoming::Gone | remote::Incoming::Command(remote::Command::Quit) => return remote::Turn::Gone,
+                remote::Incoming::Nothing if lockstep && playing && should_tick => return remote::Turn::Waiting,
+                remote::Incoming::Nothing => return remote::Turn::Ready,
+                remote::Incoming::Command(remote::Command::Reset(seed)) => {
+                    self.start_run(None, 0.0);
+                    if let Some(seed) = seed
+                    {
+                        self.ecs.write_resource::<course::Course>().reseed(seed);
+                    }
+                    link.restart();
+                    link.send_state(&self.ecs, None);
+                },
+                remote::Incoming::Command(_) if !playing => link.error("the run's over, send reset"),
+                remote::Incoming::Command(cmd) => {
+                    if cmd == remote::Command::Flap
+                    {
+                        self.ecs.write_resource::<autopilot::PlayerInput>().flap = true;
+                    }
+                    if lockstep
+                    {
+                        return remote::Turn::Ready;
+                    }
+                }
             }
-            if let None = velo.remove(ent)
+        }
+    }
+
+    fn restore_tuning(&mut self)
+    {
+        for (var, value) in std::mem::take(&mut self.tuning_backup)
+        {
+            // only fails on unknown names, and these all came from TUNING_VARS
+            let _ = tuning::set(&mut self.ecs, var, value);
+        }
+    }
+
+    /// Undo everything a run changed, the pool, the level and its tuning
+    fn stop_run(&mut self)
+    {
+        self.restore_tuning();
+        reset_pool(&mut self.ecs);
+        *self.ecs.write_resource::<levels::LevelRun>() = levels::LevelRun::default();
+        *self.ecs.write_resource::<IsGameover>() = IsGameover(false);
+    }
+
+    /// Back to the menu after a level, with the level list fresh off the disk.
+    /// Play tests go back to the editor instead
+    fn back_to_menu(&mut self, ctx: &mut Context)
+    {
+        self.stop_run();
+        if let Some(editor) = &mut self.editor
+        {
+            if editor.playtesting
             {
-                // nothing happens...
+                editor.playtesting = false;
+                self.state = State::Editor;
+                return;
             }
-            continue;
-        
         }
 
-        let mut score = self.ecs.write_resource::<Score>();
-        *score = Score(0);
-        let mut is_gameover = self.ecs.write_resource::<IsGameover>();
-        *is_gameover = IsGameover(false);
+        for err in levels::load(ctx, &mut self.ecs)
+        {
+            self.console.print(&err);
+        }
+        self.state = State::Menu;
+    }
 
-        self.state = State::Playing;
+    /// Edit the level selected in the menu, or a new one when endless is selected
+    fn open_editor(&mut self)
+    {
+        let editor = match self.ecs.read_resource::<levels::LevelList>().selected_level()
+        {
+            Some(level) => editor::Editor::new(level.clone(), &self.asset_dir),
+            None => editor::Editor::new_level(&self.asset_dir)
+        };
+        self.editor = Some(editor);
+        self.state = State::Editor;
+    }
+
+    fn close_editor(&mut self, ctx: &mut Context)
+    {
+        // select whatever was being edited so it's ready to play
+        let file = self.editor.take().map(|e| e.level.file);
+        self.back_to_menu(ctx);
+        if let Some(file) = file
+        {
+            self.ecs.write_resource::<levels::LevelList>().select(&file);
+        }
+    }
+
+    fn playtest(&mut self)
+    {
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+        editor.playtesting = true;
+        let (level, from) = (editor.sorted_level(), editor.scroll);
+        self.start_run(Some(level), from);
+    }
+
+    fn draw_console(&self, ctx: &mut Context) -> GameResult
+    {
+        if !self.console.open
+        {
+            return Ok(());
+        }
+
+        let mut lines = self.console.visible().join("\n");
+        if self.console.scrolled() > 0
+        {
+            lines.push_str(&format!("\n... {} newer lines, PageDown", self.console.scrolled()));
+        }
+        lines.push_str(&format!("\n> {}_", self.console.input));
+        let text = graphics::Text::new((lines, self.font, 22.0));
+
+        // grows with the log so a full page always fits
+        let height = (WINDOW_H*0.4).max(text.dimensions(ctx).h + 20.0);
+        let bg = Mesh::new_rectangle(ctx,
+            DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, WINDOW_W, height),
+            Color::new(0.0, 0.0, 0.0, 0.8)
+        )?;
+        graphics::draw(ctx, &bg, DrawParam::default())?;
+
+        graphics::draw(ctx,
+            &text,
+            graphics::DrawParam::new()
+                    .dest(Point2::new(10.0, height - text.dimensions(ctx).h - 10.0))
+                    .color(Color::from((0, 255, 0, 255)))
+            )?;
+        Ok(())
+    }
+
+    /// Rebuild whatever depends on an asset that changed on disk
+    fn reload_asset(&mut self, ctx: &mut Context, asset: &str) -> GameResult
+    {
+        match asset
+        {
+            PLAYER_SHEET => {
+                let sheet = graphics::Image::new(ctx, PLAYER_SHEET)?;
+                let mask = collider::AlphaMask::from_image(ctx, &sheet)?;
+                let mut animation = self.ecs.write_storage::<Animation>();
+                let mut colliders = self.ecs.write_storage::<collider::Collider>();
+                let control = self.ecs.read_storage::<Controllable>();
+                for (anim, _) in (&mut animation, &control).join()
+                {
+                    anim.spritesheet.replace_sheet(sheet.clone());
+                }
+                for (c, _) in (&mut colliders, &control).join()
+                {
+                    c.mask = Some(mask.clone());
+                }
+            },
+            OBSTACLE_SHEET => {
+                self.obst_sheet = graphics::Image::new(ctx, OBSTACLE_SHEET)?;
+            },
+            tuning::TUNING_FILE => {
+                for err in tuning::load(ctx, &mut self.ecs)
+                {
+                    self.console.print(&err);
+                }
+            },
+            patterns::PATTERN_FILE => {
+                for err in patterns::load(ctx, &mut self.ecs)
+                {
+                    self.console.print(&err);
+                }
+            },
+            // any clip changing reloads the whole config, it's cheap enough
+            _ if asset == mixer::AUDIO_FILE || self.ecs.read_resource::<mixer::AudioManager>().clip_files().iter().any(|c| c == asset) => {
+                for err in mixer::load(ctx, &mut self.ecs)
+                {
+                    self.console.print(&err);
+                }
+                // the config might name clips that weren't there before
+                for clip in self.ecs.read_resource::<mixer::AudioManager>().clip_files()
+                {
+                    self.hot_reload.watch(clip);
+                }
+            },
+            _ => {}
+        }
+        self.console.print(&format!("reloaded {asset}"));
+        Ok(())
     }
 }
 
@@ -245,57 +572,117 @@ impl ggez::event::EventHandler<GameError> for GameState
     fn update(&mut self, ctx: &mut Context) -> GameResult
     {
         let delta = timer::delta(ctx);
+
+        for asset in self.hot_reload.poll(delta)
+        {
+            if let Err(e) = self.reload_asset(ctx, &asset)
+            {
+                self.console.print(&format!("couldn't reload {asset}: {e}"));
+            }
+        }
         
         if self.ecs.read_resource::<IsGameover>().0
         {
             self.state = State::GameOver;
         }
-
-        match self.state
+        else if self.ecs.read_resource::<levels::LevelRun>().complete
         {
-            State::Playing => {    
-                {   // we do these in their own scope as the systems need &mut btw
-                    // UPDATE GAME STATE
-                    let mut input_state = self.ecs.write_resource::<Delta>();
-                    *input_state = Delta(delta);
-                    
-                    // UPDATE PHYSICS / FALLING
-                    
-                    let grav = self.ecs.read_resource::<Gravity>();
-                    let mut velo  = self.ecs.write_storage::<Velocity>();
-                    let control = self.ecs.read_storage::<Controllable>();
-            
-                    for (vel, _) in (&mut velo, &control).join()
-                    {
-                        let dt = delta.as_secs_f32();
-                        vel.y += grav.0 * dt;
-                    }
-
-                    // UPDATE ANIMATIONS
+            self.state = State::LevelComplete;
+        }
 
-                    let mut animation  = self.ecs.write_storage::<Animation>();
-                    for anim in (&mut animation).join()
-                    {
-                        anim.spritesheet.tick();
-                    }
-                }
-                if RUN_SYS_MOVEMENT
+        {   // EVOLUTION
+            // a generation's over when every bird's dead, the next one goes straight in
+            if self.ecs.read_resource::<evolution::Population>().active && matches!(self.state, State::GameOver)
+            {
+                for err in evolution::next_generation(&mut self.ecs)
                 {
-                    self.movement_sys.run_now(&self.ecs);
+                    self.console.print(&err);
                 }
-                if RUN_SYS_OBSTACLES
+            }
+            if evolution::take_restart(&self.ecs)
+            {
+                self.start_run(None, 0.0);
+            }
+        }
+
+        {   // ATTRACT MODE
+            if self.attract && !matches!(self.state, State::Playing)
+            {
+                // the demo's over when the bot dies, the menu starts it again later
+                self.stop_attract(ctx);
+            }
+            else if matches!(self.state, State::Menu) && !self.console.open && self.remote.is_none()
+            {
+                self.menu_idle += delta.as_secs_f32();
+                if self.menu_idle > ATTRACT_DELAY
                 {
-                    self.obstacle_sys.run_now(&self.ecs);
+                    self.start_attract();
                 }
-                if RUN_SYS_COLLISION
+            }
+            else
+            {
+                self.menu_idle = 0.0;
+            }
+        }
+
+        let mut should_tick = self.ecs.write_resource::<TimeScale>().should_tick();
+        if let Some(mut link) = self.remote.take()
+        {
+            match self.drive_remote(&mut link, should_tick)
+            {
+                remote::Turn::Ready => self.remote = Some(link),
+                remote::Turn::Waiting => {
+                    should_tick = false;
+                    self.remote = Some(link);
+                },
+                remote::Turn::Gone => self.console.print("the bot's gone")
+            }
+        }
+        if let (State::Playing, true) = (&self.state, should_tick)
+        {
+            {   // UPDATE GAME STATE
+                let mut input_state = self.ecs.write_resource::<Delta>();
+                *input_state = Delta(delta);
+            }
+            self.dispatcher.dispatch(&self.ecs);
+            let errors = std::mem::take(&mut self.ecs.write_resource::<SystemErrors>().0);
+            for err in errors
+            {
+                self.console.print(&err);
+            }
+            if let Some(link) = &mut self.remote
+            {
+                link.send_state(&self.ecs, None);
+            }
+
+            let events = self.ecs.write_resource::<levels::LevelRun>().take_events();
+            for (var, value) in events
+            {
+                if let Err(err) = tuning::set(&mut self.ecs, &var, value)
                 {
-                    self.collision_sys.run_now(&self.ecs);
+                    self.console.print(&err);
                 }
-                
-                self.score_sys.run_now(&self.ecs);
-            
             }
-            _ => {}
+        }
+
+        {   // AUDIO
+            let scene = match self.state
+            {
+                State::Menu | State::Editor => mixer::Scene::Menu,
+                State::Playing | State::GameOver | State::LevelComplete => mixer::Scene::Game
+            };
+            let paused = self.ecs.read_resource::<TimeScale>().frozen;
+            let mut audio = self.ecs.write_resource::<mixer::AudioManager>();
+            audio.set_scene(scene);
+            // the stems only play while the game's actually going
+            if paused || !matches!(self.state, State::Playing)
+            {
+                audio.set_intensity(0.0);
+            }
+            for err in audio.update(ctx, delta.as_secs_f32())
+            {
+                self.console.print(&err);
+            }
         }
 
         self.ecs.maintain();
@@ -307,13 +694,25 @@ impl ggez::event::EventHandler<GameError> for GameState
     {
         graphics::clear(ctx, [1.0;4].into());
 
+        if let (State::Editor, Some(editor)) = (&self.state, &self.editor)
+        {
+            editor.draw(ctx, self.font)?;
+            self.draw_console(ctx)?;
+            return graphics::present(ctx);
+        }
+
         let rect = self.ecs.read_storage::<Rect>();
 
         //  ---------- PLAYER -------------
 
         let velo  = self.ecs.read_storage::<Velocity>();
         let animation  = self.ecs.read_storage::<Animation>();
-        for (anim, r, v) in (&animation, &rect, &velo).join()
+        let dead  = self.ecs.read_storage::<Dead>();
+        let gameover = self.ecs.read_resource::<IsGameover>().0;
+        // dead birds drop out of sight, except the ones left when it's over
+        let birds = || (&animation, &rect, &velo, dead.maybe()).join()
+            .filter(|(_, _, _, dead)| gameover || dead.is_none());
+        for (anim, r, v, _) in birds()
         {
             // bird sprite
             let drawparams = graphics::DrawParam::new()
@@ -326,10 +725,14 @@ impl ggez::event::EventHandler<GameError> for GameState
                 .rotation(translate_player_rotation(&v.y))
                 .src(anim.spritesheet.draw());
             graphics::draw(ctx, &anim.spritesheet.sheet, drawparams)?;
-            
+        }
+
+        // the score hangs off whichever bird's still going
+        if let Some((anim, r, _, _)) = birds().next()
+        {
             // score text
             let score = self.ecs.read_resource::<Score>();
-            let font = graphics::Font::new(ctx, "/font.ttf")?;
+            let font = self.font;
             let text = graphics::Text::new(
                 (score.0.to_string(),
                 font, 
@@ -344,25 +747,47 @@ impl ggez::event::EventHandler<GameError> for GameState
                         ))
                         .color(Color::from((0, 0, 0, 255)))
                 )?;
+
+            // combo counter under it, gone as soon as a sloppy pass breaks it
+            let combo = self.ecs.read_resource::<scoring::Combo>();
+            if combo.count > 0
+            {
+                let text = graphics::Text::new((format!("COMBO {} x{}", combo.count, combo.factor()), font, 30.0));
+                graphics::draw(ctx,
+                    &text,
+                    graphics::DrawParam::new()
+                        .dest(Point2::new(
+                            r.pos_x + anim.spritesheet.sprite_size.0/2.0,
+                            r.pos_y + anim.spritesheet.sprite_size.1 + 90.0
+                        ))
+                        .color(Color::from((0, 0, 0, 255)))
+                )?;
+            }
         }
 
         //  --------------------------------
 
         //  --------- OBSTACLES ------------
 
-        let obst= self.ecs.read_storage::<Obstacle>();
+        let hitboxes = self.ecs.read_storage::<ObstacleHitbox>();
+        let states = self.ecs.read_storage::<ObstacleState>();
         let mut batch = SpriteBatch::new(self.obst_sheet.clone());
 
         // TODO this could be done not here like animations
-        for (_, r, _) in (&obst, &rect, &velo).join()
+        for (hitbox, r) in (&hitboxes, &rect).join()
         {
+            if let Some(ObstacleState::Idle) = states.get(hitbox.pair)
+            {
+                continue;
+            }
+
             // sausage sprites
             // basically figure out a bunch of positional translations from the sprite images
             // TODO this should be frontloaded
             let img_size = (self.obst_sheet.width() as f32,self.obst_sheet.height() as f32);
             let corrected_size = (
                 510.0 / img_size.0,
-                110.0 / img_size.1 as f32
+                110.0 / img_size.1
             );
             let corrected_pos = (
                 0.0 / img_size.0,
@@ -370,7 +795,7 @@ impl ggez::event::EventHandler<GameError> for GameState
             );
             let corrected_size_head = (
                 110.0 / img_size.0,
-                110.0 / img_size.1 as f32
+                110.0 / img_size.1
             );
             let corrected_pos_head = (
                 0.0 / img_size.0,
@@ -378,7 +803,7 @@ impl ggez::event::EventHandler<GameError> for GameState
             );
 
             // are we displaying a downwards or an upwards saus
-            let (y_offs, nega) = if r.pos_y == 0.0
+            let (y_offs, nega) = if hitbox.part == ObstaclePart::Top
             {
                 (r.size_y, -1.0)
             }
@@ -393,7 +818,7 @@ impl ggez::event::EventHandler<GameError> for GameState
                     y_offs + 50.0 * nega
                 ])
                 .offset(Point2::new(0.5, 0.5))
-                .rotation(-1.5708 * nega)
+                .rotation(-FRAC_PI_2 * nega)
                 .src(graphics::Rect::new(
                     corrected_pos_head.0,
                     corrected_pos_head.1,
@@ -407,7 +832,7 @@ impl ggez::event::EventHandler<GameError> for GameState
                     y_offs + ((WINDOW_H/4.0) + (85.0)) * nega
                 ])
                 .offset(Point2::new(0.5, 0.5))
-                .rotation(-1.5708 * nega)
+                .rotation(-FRAC_PI_2 * nega)
                 .src(graphics::Rect::new(
                     corrected_pos.0,
                     corrected_pos.1,
@@ -416,10 +841,11 @@ impl ggez::event::EventHandler<GameError> for GameState
             ));
 
             // sausage head logic
-            if (r.pos_y == 0.0 && r.size_y > 400.0) 
-                || (r.pos_y != 0.0 && r.pos_y < 450.0)
+            let is_top = hitbox.part == ObstaclePart::Top;
+            if (is_top && r.size_y > 400.0) 
+                || (!is_top && r.pos_y < 450.0)
             {
-                if r.pos_y == 0.0
+                if is_top
                 {
                     let drawparams = graphics::DrawParam::new()
                         .dest([
@@ -427,7 +853,7 @@ impl ggez::event::EventHandler<GameError> for GameState
                             r.pos_y - (WINDOW_H/2.0 - r.size_y)
                         ])
                         .offset(Point2::new(0.5, 0.5))
-                        .rotation(-1.5708 * nega)
+                        .rotation(-FRAC_PI_2 * nega)
                         .src(graphics::Rect::new(
                             corrected_pos.0,
                             corrected_pos.1,
@@ -444,7 +870,7 @@ impl ggez::event::EventHandler<GameError> for GameState
                             r.pos_y + r.size_y
                         ])
                         .offset(Point2::new(0.5, 0.5))
-                        .rotation(-1.5708 * nega)
+                        .rotation(-FRAC_PI_2 * nega)
                         .src(graphics::Rect::new(
                             corrected_pos.0,
                             corrected_pos.1,
@@ -463,13 +889,61 @@ impl ggez::event::EventHandler<GameError> for GameState
         
         //  --------------------------------
 
+        //  ---------- FINISH LINE -------------
+
+        if let Some(finish_x) = self.ecs.read_resource::<levels::LevelRun>().finish_x()
+        {
+            draw_finish_line(ctx, finish_x)?;
+        }
+
+        //  -------------------------------
+
+        //  ---------- SCORE POPUPS -------------
+
+        for popup in &self.ecs.read_resource::<scoring::ScorePopups>().0
+        {
+            let (pos, alpha) = popup.placement();
+            let text = graphics::Text::new((popup.text.as_str(), self.font, 40.0));
+            graphics::draw(ctx,
+                &text,
+                graphics::DrawParam::new()
+                    .dest(pos)
+                    .offset(Point2::new(0.5, 1.0))
+                    .color(Color::new(1.0, 1.0, 1.0, alpha))
+            )?;
+        }
+
+        //  -------------------------------
+
         //  ---------- HITBOXES -------------
         
-        if SHOW_HITBOXES
+        if self.ecs.read_resource::<DebugSettings>().show_hitboxes
         {
             let mut mb = MeshBuilder::new();
-            for r in (&rect).join()
+            let colliders = self.ecs.read_storage::<collider::Collider>();
+            let triggers = self.ecs.read_storage::<triggers::Trigger>();
+            for (r, c, v, t) in (&rect, colliders.maybe(), velo.maybe(), triggers.maybe()).join()
             {
+                // triggers don't block anything, just outline them
+                if t.is_some()
+                {
+                    let bounds = graphics::Rect::new(r.pos_x, r.pos_y, r.size_x, r.size_y);
+                    if let Err(e) = mb.rectangle(DrawMode::stroke(2.0), bounds, Color::new(1.0, 1.0, 0.0, 1.0))
+                    {
+                        eprintln!("Couldn't create mesh on error {e}");
+                    }
+                    continue;
+                }
+                // shaped colliders are what actually gets hit, so show those instead of the Rect
+                if let Some(c) = c
+                {
+                    let pose = c.pose(r, v.map_or(0.0, |v| v.y));
+                    if let Err(e) = c.outline(&mut mb, pose, Color::new(0.0, 1.0, 0.0, 1.0))
+                    {
+                        eprintln!("Couldn't create mesh on error {e}");
+                    }
+                    continue;
+                }
                 if let Err(e) = mb.polygon(DrawMode::fill(),
                     &[
                         Vec2::new(r.pos_x, r.pos_y),
@@ -479,7 +953,7 @@ impl ggez::event::EventHandler<GameError> for GameState
                     Color::new(1.0, 0.0, 1.0, 1.0),
                 )
                 {
-                    println!("Couldn't create mesh on error {e}");
+                    eprintln!("Couldn't create mesh on error {e}");
                 };
             }
             let mesh = mb.build(ctx).unwrap();
@@ -495,13 +969,32 @@ impl ggez::event::EventHandler<GameError> for GameState
         
         //  -------------------------------
 
-        
+        //  ---------- DEBUG INFO -------------
+
+        let indicators: Vec<String> = [
+            self.ecs.read_resource::<DebugSettings>().indicator(),
+            self.ecs.read_resource::<TimeScale>().indicator()
+        ].into_iter().flatten().collect();
+        if !indicators.is_empty()
+        {
+            let font = self.font;
+            let text = graphics::Text::new((indicators.join("\n"), font, 24.0));
+            graphics::draw(ctx,
+                &text,
+                graphics::DrawParam::new()
+                        .dest(Point2::new(10.0, SQUARE_SIZE/2.0 + 10.0))
+                        .color(Color::from((255, 0, 255, 255)))
+                )?;
+        }
+
+        //  -------------------------------
+
         //  --------- MENU THINGS -----------
 
         match self.state
         {
             State::Menu => {
-                let font = graphics::Font::new(ctx, "/font.ttf")?;
+                let font = self.font;
                 let text = graphics::Text::new(
                     ("WELCOME TO FLAPPY DAPPY.\n MOYSE CLICK MOVES U UP\n\nTRY TO AVOID THE SAUSAGES",
                     font, 
@@ -516,10 +1009,48 @@ impl ggez::event::EventHandler<GameError> for GameState
                         ))
                         .color(Color::from((0, 0, 0, 255)))
                 )?;
+
+                // level select
+                let levels = self.ecs.read_resource::<levels::LevelList>();
+                let text = graphics::Text::new(
+                    (format!("< {} >\nUP/DOWN TO PICK A LEVEL, E TO EDIT IT", levels.selected_name().to_uppercase()),
+                    font,
+                    40.0
+                ));
+                graphics::draw(ctx,
+                &text,
+                graphics::DrawParam::new()
+                        .dest(Point2::new(
+                            WINDOW_W/2.0-text.dimensions(ctx).w/2.0,
+                            WINDOW_H*0.8
+                        ))
+                        .color(Color::from((0, 0, 0, 255)))
+                )?;
+            },
+            State::LevelComplete => {
+                let score = self.ecs.read_resource::<Score>().0;
+                let run = self.ecs.read_resource::<levels::LevelRun>();
+                let name = run.level.as_ref().map_or("", |l| l.name.as_str());
+                let font = self.font;
+                let text = graphics::Text::new(
+                    (format!("{} COMPLETE\n\nUR SCORE WAS {}\n\n\nCLICK MOUSE FOR MENU", name.to_uppercase(), score),
+                    font,
+                    60.0
+                ));
+
+                graphics::draw(ctx,
+                &text,
+                graphics::DrawParam::new()
+                        .dest(Point2::new(
+                            WINDOW_W/2.0-text.dimensions(ctx).w/2.0,
+                            WINDOW_H/2.0
+                        ))
+                        .color(Color::from((0, 0, 0, 255)))
+                )?;
             },
             State::GameOver => {
                 let score = self.ecs.read_resource::<Score>().0;
-                let font = graphics::Font::new(ctx, "/font.ttf")?;
+                let font = self.font;
                 let text = graphics::Text::new(
                     (format!("UR TRASH \n\nUR SCORE WAS {}\n\n\nCLICK MOUSE TO RESET", score),
                     font, 
@@ -536,9 +1067,42 @@ impl ggez::event::EventHandler<GameError> for GameState
                         .color(Color::from((0, 0, 0, 255)))
                 )?;
             }
+            State::Playing if self.ecs.read_resource::<evolution::Population>().active => {
+                let population = self.ecs.read_resource::<evolution::Population>();
+                let text = graphics::Text::new((
+                    format!("GEN {}  ALIVE {}/{}  LAST BEST {:.0}",
+                        population.generation, population.alive(&self.ecs), population.members.len(), population.best),
+                    self.font,
+                    30.0
+                ));
+                graphics::draw(ctx,
+                &text,
+                graphics::DrawParam::new()
+                        .dest(Point2::new(20.0, WINDOW_H - SQUARE_SIZE - 20.0))
+                        .color(Color::from((0, 0, 0, 255)))
+                )?;
+            },
+            State::Playing if self.attract => {
+                let text = graphics::Text::new(("DEMO - CLICK TO PLAY", self.font, 40.0));
+                graphics::draw(ctx,
+                &text,
+                graphics::DrawParam::new()
+                        .dest(Point2::new(
+                            WINDOW_W/2.0-text.dimensions(ctx).w/2.0,
+                            WINDOW_H*0.85
+                        ))
+                        .color(Color::from((0, 0, 0, 255)))
+                )?;
+            },
             _ => {}
         }
         
+        //  -------------------------------
+
+        //  ----------- CONSOLE -------------
+
+        self.draw_console(ctx)?;
+
         //  -------------------------------
         
         graphics::present(ctx)?;
@@ -549,72 +1113,346 @@ impl ggez::event::EventHandler<GameError> for GameState
         &mut self, 
         ctx: &mut ggez::Context,
         key: event::KeyCode, 
-        _kmod: ggez::event::KeyMods, 
-        _repeat: bool)
+        kmod: ggez::event::KeyMods, 
+        repeat: bool)
     {
-        if !_repeat
+        if self.console.open
+        {
+            match key
+            {
+                event::KeyCode::Grave | event::KeyCode::Escape => self.console.toggle(),
+                event::KeyCode::Return => self.console.submit(&mut self.ecs, &mut self.state),
+                event::KeyCode::Back => self.console.backspace(),
+                event::KeyCode::Up => self.console.history_prev(),
+                event::KeyCode::Down => self.console.history_next(),
+                event::KeyCode::PageUp => self.console.page_up(),
+                event::KeyCode::PageDown => self.console.page_down(),
+                _ => {}
+            }
+            return;
+        }
+
+        self.menu_idle = 0.0;
+        if self.attract && key != event::KeyCode::Grave
+        {
+            self.stop_attract(ctx);
+            return;
+        }
+
+        if let (State::Editor, Some(editor), false) = (&self.state, &mut self.editor, key == event::KeyCode::Grave)
+        {
+            // scrubbing wants key repeat, so this goes before the repeat check
+            match editor.key(key, kmod, repeat)
+            {
+                editor::EditorAction::None => {},
+                editor::EditorAction::Message(msg) => self.console.print(&msg),
+                editor::EditorAction::Playtest => self.playtest(),
+                editor::EditorAction::Save => {
+                    let result = editor.save();
+                    self.console.print(&result.unwrap_or_else(|e| e));
+                },
+                editor::EditorAction::Exit => self.close_editor(ctx)
+            }
+            return;
+        }
+
+        if !repeat
         {
             match key 
             {
+                event::KeyCode::Escape if self.editor.as_ref().is_some_and(|e| e.playtesting) => {
+                    self.back_to_menu(ctx);
+                },
                 event::KeyCode::Escape => { //exit game
                     ctx.continuing = false;
                 },
-                _ => {}
+                event::KeyCode::E if matches!(self.state, State::Menu) => {
+                    self.ecs.write_resource::<mixer::AudioManager>().trigger(mixer::SoundEvent::Select);
+                    self.open_editor();
+                },
+                event::KeyCode::Grave => {
+                    self.console.toggle();
+                },
+                event::KeyCode::Up | event::KeyCode::Left if matches!(self.state, State::Menu) => {
+                    self.ecs.write_resource::<levels::LevelList>().select_prev();
+                    self.ecs.write_resource::<mixer::AudioManager>().trigger(mixer::SoundEvent::Click);
+                },
+                event::KeyCode::Down | event::KeyCode::Right if matches!(self.state, State::Menu) => {
+                    self.ecs.write_resource::<levels::LevelList>().select_next();
+                    self.ecs.write_resource::<mixer::AudioManager>().trigger(mixer::SoundEvent::Click);
+                },
+                event::KeyCode::M => {
+                    let mut audio = self.ecs.write_resource::<mixer::AudioManager>();
+                    audio.toggle_mute();
+                    self.console.print(if audio.volumes.muted { "muted" } else { "unmuted" });
+                },
+                _ => {
+                    if !self.ecs.write_resource::<DebugSettings>().toggle(key)
+                    {
+                        self.ecs.write_resource::<TimeScale>().handle_key(key);
+                    }
+                }
             }
         }
     }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char)
+    {
+        if self.console.open
+        {
+            self.console.push_char(character);
+        }
+    }
     
     fn mouse_button_down_event(
         &mut self,
         ctx: &mut Context,
         button: MouseButton,
-        _x: f32,
-        _y: f32
+        x: f32,
+        y: f32
     )
     {
+        if let (State::Editor, Some(editor)) = (&self.state, &mut self.editor)
+        {
+            editor.mouse_down(button, x, y);
+            return;
+        }
+
+        self.menu_idle = 0.0;
+        if self.attract
+        {
+            self.stop_attract(ctx);
+            return;
+        }
+
         match button
         {
             MouseButton::Left => 
             {
+                // everything but flapping is picking something in a menu, flaps make their own sound
+                if !matches!(self.state, State::Playing | State::Editor)
+                {
+                    self.ecs.write_resource::<mixer::AudioManager>().trigger(mixer::SoundEvent::Select);
+                }
+
                 match self.state 
                 {
                     State::Menu => {
-                        self.state = State::Playing;
+                        self.reset_game();
                     },
                     State::Playing => {
-                        let mut velo = self.ecs.write_storage::<Velocity>();
-                        let mut animation = self.ecs.write_storage::<Animation>();
-                        let control = self.ecs.read_storage::<Controllable>();
-                        let mut sound = self.ecs.write_storage::<Sound>();
-                        for (vel, _, anim, s) 
-                            in (&mut velo, &control, &mut animation, &mut sound).join()
-                        {
-                            if vel.y > BIRD_FLAP-(BIRD_FLAP*0.3)// we do an extra chec kto double the jump vel
-                            {                                   // so it feels a bit better to control
-                                vel.y -= BIRD_FLAP*2.0;
-                            }
-                            else 
-                            {
-                                vel.y -= BIRD_FLAP;
-                            }
-                            anim.spritesheet.start_animation("flap");
-                            
-                            let mut rng = rand::thread_rng();
-                            let indx = rng.gen_range::<usize,usize,usize>(0, 2);
-                            if let Err(e) = s.sounds[indx].play(ctx)
-                            {
-                                println!("SOUNDS ERROR {e}");
-                            }
-                        }
+                        // PilotSystem flaps it next tick, if the player's the one flying
+                        self.ecs.write_resource::<autopilot::PlayerInput>().flap = true;
+                    },
+                    State::GameOver if self.editor.as_ref().is_some_and(|e| e.playtesting) => {
+                        self.back_to_menu(ctx);
                     },
                     State::GameOver => {
                         self.reset_game();
-                    }
+                    },
+                    State::LevelComplete => {
+                        self.back_to_menu(ctx);
+                    },
+                    State::Editor => {}
                 }
             },
             _ => {}
         }
     }
+
+    fn mouse_button_up_event(&mut self, _ctx: &mut Context, _button: MouseButton, _x: f32, _y: f32)
+    {
+        if let (State::Editor, Some(editor)) = (&self.state, &mut self.editor)
+        {
+            editor.mouse_up();
+        }
+    }
+
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32)
+    {
+        if let (State::Editor, Some(editor)) = (&self.state, &mut self.editor)
+        {
+            editor.mouse_moved(x, y);
+        }
+    }
+
+    fn mouse_wheel_event(&mut self, _ctx: &mut Context, _x: f32, y: f32)
+    {
+        if let (State::Editor, Some(editor)) = (&self.state, &mut self.editor)
+        {
+            editor.wheel(y);
+        }
+    }
+}
+
+/// The World with every resource and component the simulation uses but nothing in it yet,
+/// shared by the game and the headless gym::Env
+fn create_world(dispatcher: &mut Dispatcher) -> World
+{
+    let mut world = World::new();
+    dispatcher.setup(&mut world);
+
+    world.insert(Delta(Duration::from_nanos(0)));
+    world.insert(Gravity(GRAVITY));
+    world.insert(FlapStrength(BIRD_FLAP));
+    world.insert(ObstacleSpeed(OBST_SPEED));
+    world.insert(Score(0));
+    world.insert(Difficulty(0));
+    world.insert(IsGameover(false));
+    world.insert(SystemErrors::default());
+    world.insert(CollisionStats::default());
+    world.insert(scoring::ScoreClaims::default());
+    world.insert(scoring::Combo::default());
+    world.insert(scoring::ScoreMultipliers::default());
+    world.insert(scoring::ScorePopups::default());
+    world.insert(scoring::ScoreStats::default());
+    world.insert(DebugSettings::default());
+    world.insert(TimeScale::default());
+    world.insert(levels::LevelRun::default());
+    world.insert(evolution::Population::default());
+    
+    world.register::<Rect>();
+    world.register::<LastRect>();
+    world.register::<Boundary>();
+    world.register::<ObstaclePair>();
+    world.register::<ObstacleState>();
+    world.register::<ObstacleHitbox>();
+    world.register::<ObstacleGate>();
+    world.register::<triggers::Trigger>();
+    world.register::<behaviours::Oscillate>();
+    world.register::<behaviours::Breathe>();
+    world.register::<behaviours::DropIn>();
+    world.register::<Velocity>();
+    world.register::<Animation>();
+    world.register::<Collision>();
+    world.register::<Controllable>();
+    world.register::<Dead>();
+    world.register::<Progress>();
+    world.register::<autopilot::Pilot>();
+    world.register::<collider::Collider>();
+
+    world
+}
+
+/// The bird without a sprite, the game adds its Animation before building it
+fn create_bird(world: &mut World, collider: collider::Collider) -> EntityBuilder<'_>
+{
+    world
+        .create_entity()
+        .with(Rect{ pos_x: BIRD_X, pos_y: BIRD_Y, 
+            size_x: SQUARE_SIZE, size_y: SQUARE_SIZE})
+        .with(collider)
+        .with(Velocity { x: 0.0, y: 0.0 })
+        .with(Collision)
+        .with(Controllable)
+        .with(Progress::default())
+        .with(autopilot::Pilot::Player)
+}
+
+/// Ceiling, floor and the obstacle pool
+fn create_bounds(world: &mut World)
+{
+    world
+        .create_entity()
+        .with(Rect{ pos_x: -1.0, pos_y: -1.0,
+            size_x: WINDOW_W + 1.0, size_y: SQUARE_SIZE/2.0})
+        .with(Collision)
+        .with(Boundary::Ceiling)
+        .build();
+        
+    world
+        .create_entity()
+        .with(Rect{ pos_x: -1.0, pos_y: WINDOW_H - (SQUARE_SIZE/2.0),
+            size_x: WINDOW_W + 1.0, size_y: SQUARE_SIZE/2.0})
+        .with(Collision)
+        .with(Boundary::Floor)
+        .build();
+
+    create_pool(world, ObstaclePool::default());
+}
+
+/// Everything a run changes goes back to how it starts, the bird, the obstacles and the score
+fn reset_world(world: &mut World)
+{
+    {
+        let mut rect  = world.write_storage::<Rect>();
+        let mut velo  = world.write_storage::<Velocity>();
+        let mut progress  = world.write_storage::<Progress>();
+        let controllable  = world.write_storage::<Controllable>();
+
+        for (r, vel, prog, _) in (&mut rect, &mut velo, &mut progress, &controllable).join()
+        {
+            *r = Rect{ pos_x: BIRD_X, pos_y: BIRD_Y, size_x: SQUARE_SIZE, size_y: SQUARE_SIZE};
+            *vel = Velocity { x: 0.0, y: 0.0 };
+            *prog = Progress::default();
+        }
+        world.write_storage::<Dead>().clear();
+        // everything jumps back to the start, none of it moved there
+        world.write_storage::<LastRect>().clear();
+    }
+
+    reset_pool(world);
+
+    *world.write_resource::<Score>() = Score(0);
+    *world.write_resource::<Difficulty>() = Difficulty(0);
+    *world.write_resource::<IsGameover>() = IsGameover(false);
+    *world.write_resource::<CollisionStats>() = CollisionStats::default();
+    *world.write_resource::<scoring::ScoreClaims>() = scoring::ScoreClaims::default();
+    *world.write_resource::<scoring::Combo>() = scoring::Combo::default();
+    *world.write_resource::<scoring::ScoreMultipliers>() = scoring::ScoreMultipliers::default();
+    *world.write_resource::<scoring::ScorePopups>() = scoring::ScorePopups::default();
+    *world.write_resource::<scoring::ScoreStats>() = scoring::ScoreStats::default();
+}
+
+/// Read a whole asset through the ggez filesystem, None if it isn't there
+fn read_asset(ctx: &mut Context, file: &str) -> Result<Option<String>, String>
+{
+    let mut contents = String::new();
+    match filesystem::open(ctx, file)
+    {
+        Ok(mut f) => std::io::Read::read_to_string(&mut f, &mut contents)
+            .map(|_| Some(contents))
+            .map_err(|e| format!("couldn't read {file}: {e}")),
+        Err(_) => Ok(None)
+    }
+}
+
+/// Checkered strip from the ceiling to the floor at screen x
+fn draw_finish_line(ctx: &mut Context, x: f32) -> GameResult
+{
+    let square = 20.0;
+    if x + 2.0*square < 0.0 || x > WINDOW_W
+    {
+        return Ok(());
+    }
+
+    let mut mb = MeshBuilder::new();
+    for row in 0..(WINDOW_H / square) as usize
+    {
+        for col in 0..2
+        {
+            let color = if (row + col) % 2 == 0 { Color::BLACK } else { Color::from_rgb(200, 200, 200) };
+            mb.rectangle(DrawMode::fill(),
+                graphics::Rect::new(x + col as f32 * square, row as f32 * square, square, square),
+                color
+            )?;
+        }
+    }
+    let mesh = mb.build(ctx)?;
+    graphics::draw(ctx, &mesh, DrawParam::default())
+}
+
+/// Vertical velocity right after a flap
+fn flap_velocity(vel_y: f32, flap: f32) -> f32
+{
+    if vel_y > flap-(flap*0.3)// we do an extra chec kto double the jump vel
+    {                         // so it feels a bit better to control
+        vel_y - flap*2.0
+    }
+    else 
+    {
+        vel_y - flap
+    }
 }
 
 /// Translate the player velocity to radians of rotation
@@ -637,6 +1475,30 @@ fn translate_player_rotation(vel: &f32) -> f32
 
 fn main()
 {
+    // headless, no window needed
+    let args: Vec<String> = std::env::args().collect();
+    let arg = |i: usize, default| args.get(i).and_then(|n| n.parse().ok()).unwrap_or(default);
+    let assets = path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+    match args.get(1).map(String::as_str)
+    {
+        Some("--gym-bench") => return gym::bench(arg(2, 1000), &assets),
+        Some("--evolve") => return evolution::train_headless(arg(2, 50) as u32, arg(3, 50) as usize, &assets),
+        _ => {}
+    }
+    let remote = match args.get(1).map(String::as_str)
+    {
+        Some("--remote") => match remote::Link::from_args(&args[2..])
+        {
+            Ok((link, true)) => return remote::serve_headless(link, &assets),
+            Ok((link, false)) => Some(link),
+            Err(err) => {
+                eprintln!("{err}");
+                return;
+            }
+        },
+        _ => None
+    };
+
     let mut cb = ContextBuilder::new(
         "flappydappy","NIC")
         .window_mode(WindowMode {
@@ -658,286 +1520,27 @@ fn main()
     let manifest_dir = "C:/Users/eirik/OneDrive/Desktop/CODE/RUST/GGEZ/projects/flappydappy";
     let mut path = path::PathBuf::from(manifest_dir);
     path.push("assets");
-    cb = cb.add_resource_path(path);
+    cb = cb.add_resource_path(path.clone());
     
     let (mut ctx, event_loop) = cb.build().unwrap();
     
-    let player_spritesheet_img = graphics::Image::new(&mut ctx, "/bird.png").unwrap();
+    let player_spritesheet_img = graphics::Image::new(&mut ctx, PLAYER_SHEET).unwrap();
     let mut player_spritesheet = animation::Spritesheet::new(
         player_spritesheet_img,
-        3.0,
-        (32.0,25.0),
+        BIRD_SPRITE_SCALE,
+        BIRD_SPRITE_SIZE,
         35.0
     );
 
     player_spritesheet.add_animation("idle",(145.0,145.0), 0, 0);
     player_spritesheet.add_animation_looping("flap",(5.0,144.0), 3, false, 1);
     
-    let state = GameState::new(&mut ctx, player_spritesheet).unwrap();
-
-    event::run(ctx, event_loop, state);
-}
-
-
-struct ObstacleSysten;
-impl<'a> System<'a> for ObstacleSysten
-{
-    type SystemData = (
-        Entities<'a>,
-        WriteStorage<'a, Rect>,
-        WriteStorage<'a, Dirty>,
-        ReadStorage<'a, Obstacle>,
-        WriteStorage<'a, Velocity>,
-    );
-
-    fn run(&mut self, data: Self::SystemData)
+    let mut state = GameState::new(&mut ctx, player_spritesheet, path).unwrap();
+    if let Some(mut link) = remote
     {
-        let (entity, mut rect, 
-            mut dirty, obst, 
-            mut velocity) 
-            = data;
-        
-        let mut reload_obstacles = None;
-
-        // has an obstacle gone outside the screen bounds?
-
-        'reload: for (e_outer, r, obs_outer, _) in (&entity, &rect, &obst, &velocity).join()
-        {
-            if r.pos_x + r.size_x + 50.0 < 0.0 // check for offset of when to move
-            {   
-                let id = obs_outer.0;
-                for (e_inner, obs,r_inner,  _) in (&entity, &obst, &rect, &velocity).join()
-                {
-                    if id == obs.0 && r != r_inner
-                    {
-                        if r.pos_y < r_inner.pos_y
-                        {
-                            reload_obstacles = Some((e_outer,e_inner));
-                        }
-                        else
-                        {
-                            reload_obstacles = Some((e_inner,e_outer));
-                        }
-                        dirty.remove(e_inner);
-                        dirty.remove(e_outer);
-                            
-                        break 'reload;
-                    }
-                }
-            }
-        }
-
-        if let Some((e_upper,e_lower)) = reload_obstacles
-        {   // if yes, move it to the start and remove velocity component so it can wait to be spawned
-            let mut rng = rand::thread_rng();
-            let height_from_ceiling = (WINDOW_H/2.0) * rng.gen_range::<f32, f32, f32>(RNG_LOW, RNG_HIGH);
-            
-            let mut r_upper = rect.get_mut(e_upper).unwrap();
-            r_upper.size_y = height_from_ceiling - (OBSTACLE_TIGTHFACTOR/2.0);
-            r_upper.pos_x = WINDOW_W+50.0;
-            
-            let mut r_lower = rect.get_mut(e_lower).unwrap();
-            r_lower.pos_y = height_from_ceiling+(OBSTACLE_TIGTHFACTOR/2.0);
-            r_lower.size_y = WINDOW_H - (height_from_ceiling+(OBSTACLE_TIGTHFACTOR/2.0));
-            r_lower.pos_x = WINDOW_W+50.0;
-
-            if let (None, None) = (velocity.remove(e_upper),velocity.remove(e_lower))
-            {
-                println!("wtf happened here");
-            }
-        }
-
-        let mut last_pos_x = 0.0;
-        for (r, _, _) in (&rect, &obst, &velocity).join()
-        {
-            if r.pos_x > last_pos_x
-            {
-                last_pos_x = r.pos_x;
-            }
-        }
-        
-        // is the obstacle in front of us far enough for us to join?
-        let mut spawned_ent = None;
-        'spawn: for (ent_outer, r, obs, _) in (&entity, &rect, &obst, !&velocity).join()
-        {
-            if r.pos_x-last_pos_x > (WINDOW_W/OBST_AMOUNT as f32)
-            {
-                let id = obs.0;
-                for (ent_inner, obs,r_inner,  _) in (&*entity, &obst, &rect, !&velocity).join()
-                {
-                    if id == obs.0 && r != r_inner
-                    {
-                        spawned_ent = Some(vec![
-                            ent_outer,
-                            ent_inner
-                        ]);
-                        break 'spawn;
-                    }
-                }
-            }
-        }
-
-        if let Some(ents) = spawned_ent
-        { //yes, add velocity
-            for ent in ents
-            {
-                if let Err(err) = velocity.insert(ent,
-                    Velocity { x: OBST_SPEED, y: 0.0 })
-                {
-                    println!("{err}");
-                };
-            }
-        }
-    }
-}
-
-struct CollisionSystem;
-impl<'a> System<'a> for CollisionSystem
-{
-    type SystemData = (
-        Write<'a, IsGameover>,
-        ReadStorage<'a, Rect>,
-        ReadStorage<'a, Collision>,
-        ReadStorage<'a, Controllable>,
-    );
-
-    fn run(&mut self, data: Self::SystemData)
-    {
-        let (mut is_gameover, rect,collision, contr) 
-            = data;
-
-        // straight forward box collisions
-        // https://developer.mozilla.org/en-US/docs/Games/Techniques/2D_collision_detection
-        for (r_p, _, _) in (&rect, &collision, &contr).join()
-        {
-            for (r, _, _) in (&rect, &collision, !&contr).join()
-            {
-                if  r_p.pos_x < r.pos_x + r.size_x 
-                &&  r_p.pos_x + r_p.size_x > r.pos_x
-                &&  r_p.pos_y < r.pos_y + r.size_y 
-                &&  r_p.pos_y + r_p.size_y > r.pos_y
-                {
-                    is_gameover.0 = true;
-                }
-            }
-        }
-    }
-}
-
-struct MovementSystem;
-impl<'a> System<'a> for MovementSystem
-{
-    type SystemData = (
-        WriteStorage<'a, Rect>,
-        ReadStorage<'a, Velocity>,
-    );
-
-    fn run(&mut self, data: Self::SystemData)
-    {
-        let (mut rect, velocity) 
-            = data;
-
-        for (velo, r) in (&velocity, &mut rect).join()
-        {
-            r.pos_y += velo.y;
-            r.pos_x += velo.x;
-        }
-    }
-}
-
-struct ScoreSystem;
-impl<'a> System<'a> for ScoreSystem
-{
-    type SystemData = (
-        Entities<'a>,
-        Write<'a, Score>,
-        ReadStorage<'a, Rect>,
-        WriteStorage<'a, Dirty>,
-        ReadStorage<'a, Obstacle>,
-        ReadStorage<'a, Controllable>,
-    );
-
-    fn run(&mut self, data: Self::SystemData)
-    {
-        let (entities, mut score, rect, mut dirty, obstacle, controllable) = data;
-
-        // we don't want to add a score mutliple times for the same obstacle
-        // so we use dirty component to keep track
-        let mut is_dirty = None;
-        'outer:for (r_player,_) in (&rect, &controllable).join()
-        {
-            for (r, obst, _) in (&rect, &obstacle, !&dirty).join()
-            {
-                if r_player.pos_x > r.pos_x
-                {
-                    is_dirty = Some(obst.0);
-                    break 'outer;
-                } 
-            }
-        }
-
-        if let Some(obst_id) = is_dirty
-        {
-            for (ent, obst) in (&*entities, &obstacle).join()
-            {
-                if obst.0 == obst_id
-                {
-                    if let Err(err) = dirty.insert(ent,Dirty)
-                    {
-                        println!("{err}");
-                    };
-                }
-            }
-            score.0 += 1;
-        }
+        link.hello();
+        state.remote = Some(link);
     }
-}
 
-fn create_obstacles(world: &mut World, obstacle_amount: u8)
-{
-    let mut rng = rand::thread_rng();
-    let mut is_first = true;
-    
-    //TODO make based on window _W instead of fixed amount or whatever
-    for i in 0..obstacle_amount+1
-    {
-        let height_from_ceiling = (WINDOW_H/2.0) * rng.gen_range::<f32, f32, f32>(RNG_LOW, RNG_HIGH);
-        if is_first //todo hack
-        {
-            world
-                .create_entity()
-                .with(Rect{ pos_x: WINDOW_W+50.0, pos_y: 0.0,
-                    size_x: 2.0*SQUARE_SIZE/3.0, size_y: height_from_ceiling - (OBSTACLE_TIGTHFACTOR/2.0)})
-                .with(Velocity { x: OBST_SPEED, y: 0.0 })
-                .with(Collision)
-                .with(Obstacle(i))
-                .build();
-            world
-                .create_entity()
-                .with(Rect{ pos_x: WINDOW_W+50.0, pos_y: height_from_ceiling+(OBSTACLE_TIGTHFACTOR/2.0),
-                    size_x: 2.0*SQUARE_SIZE/3.0, size_y: WINDOW_H - (height_from_ceiling+(OBSTACLE_TIGTHFACTOR/2.0))})
-                .with(Velocity { x: OBST_SPEED, y: 0.0 })
-                .with(Collision)
-                .with(Obstacle(i))
-                .build();
-        }
-        else
-        {
-            world
-                .create_entity()
-                .with(Rect{ pos_x: WINDOW_W+50.0, pos_y: 0.0,
-                    size_x: 2.0*SQUARE_SIZE/3.0, size_y: height_from_ceiling - (OBSTACLE_TIGTHFACTOR/2.0)})
-                .with(Collision)
-                .with(Obstacle(i))
-                .build();
-            world
-                .create_entity()
-                .with(Rect{ pos_x: WINDOW_W+50.0, pos_y: height_from_ceiling+(OBSTACLE_TIGTHFACTOR/2.0),
-                    size_x: 2.0*SQUARE_SIZE/3.0, size_y: WINDOW_H - (height_from_ceiling+(OBSTACLE_TIGTHFACTOR/2.0))})
-                .with(Collision)
-                .with(Obstacle(i))
-                .build();
-        }
-        is_first = false;
-    }
+    event::run(ctx, event_loop, state);
 }
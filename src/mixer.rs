@@ -0,0 +1,637 @@
+use std::io::Write;
+
+use ggez::audio::{self, SoundSource};
+use ggez::{filesystem, Context};
+use rand::Rng;
+use specs::*;
+
+use crate::read_asset;
+
+/// Which clips play for each sound event and the music for each scene, see the file for the format
+pub const AUDIO_FILE:&str = "/audio.cfg";
+/// Volumes and mute, written to the user config dir whenever they change
+pub const SETTINGS_FILE:&str = "/settings.cfg";
+
+/// Seconds for one scene's music to fade out while the next fades in
+const CROSSFADE_TIME:f32 = 1.5;
+/// Seconds for a stem to come in or drop out when the intensity crosses its level
+const STEM_FADE_TIME:f32 = 2.0;
+
+pub const VOLUME_VARS:[&str; 4] = ["master", "music", "sfx", "mute"];
+
+/// Something in the game that makes a noise, one of its clips gets picked at random
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoundEvent
+{
+    Flap,
+    Score,
+    /// the bird hit something, even if god mode kept it going
+    Hit,
+    GameOver,
+    /// moving around a menu
+    Click,
+    /// picking something in a menu
+    Select
+}
+
+impl SoundEvent
+{
+    pub fn parse(name: &str) -> Option<SoundEvent>
+    {
+        match name
+        {
+            "flap" => Some(SoundEvent::Flap),
+            "score" => Some(SoundEvent::Score),
+            "hit" => Some(SoundEvent::Hit),
+            "gameover" => Some(SoundEvent::GameOver),
+            "click" => Some(SoundEvent::Click),
+            "select" => Some(SoundEvent::Select),
+            _ => None
+        }
+    }
+
+    /// What an event gets unless the audio config says otherwise
+    pub fn default_variation(&self) -> Variation
+    {
+        let (pitch, volume, voices, cooldown) = match self
+        {
+            SoundEvent::Flap => (0.08, 0.15, 3, 0.0),
+            SoundEvent::Score => (0.05, 0.1, 2, 0.05),
+            // god mode can be in a wall for a while, don't hit every tick
+            SoundEvent::Hit => (0.1, 0.2, 2, 0.25),
+            SoundEvent::GameOver => (0.0, 0.0, 1, 1.0),
+            SoundEvent::Click => (0.1, 0.1, 2, 0.0),
+            SoundEvent::Select => (0.03, 0.0, 1, 0.0)
+        };
+        Variation { pitch, volume, voices, cooldown }
+    }
+}
+
+/// How an event's clips get played
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Variation
+{
+    /// pitch goes up or down by up to this, 0.1 is anywhere from 0.9x to 1.1x
+    pub pitch: f32,
+    /// volume goes down by up to this fraction
+    pub volume: f32,
+    /// at most this many play at once, the oldest one gets cut off to make room
+    pub voices: usize,
+    /// seconds before the event can play again, anything sooner is dropped
+    pub cooldown: f32
+}
+
+/// Every scene has its own music
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scene
+{
+    Menu,
+    Game
+}
+
+impl Scene
+{
+    pub fn parse(name: &str) -> Option<Scene>
+    {
+        match name
+        {
+            "menu" => Some(Scene::Menu),
+            "game" => Some(Scene::Game),
+            _ => None
+        }
+    }
+}
+
+/// Music and sound effects each have their own volume on top of the master one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Volumes
+{
+    pub master: f32,
+    pub music: f32,
+    pub sfx: f32,
+    pub muted: bool
+}
+
+impl Default for Volumes
+{
+    fn default() -> Self
+    {
+        Volumes {
+            master: 1.0,
+            music: 0.6,
+            sfx: 1.0,
+            muted: false
+        }
+    }
+}
+
+impl Volumes
+{
+    pub fn music_gain(&self) -> f32
+    {
+        if self.muted { 0.0 } else { self.master * self.music }
+    }
+
+    pub fn sfx_gain(&self) -> f32
+    {
+        if self.muted { 0.0 } else { self.master * self.sfx }
+    }
+
+    pub fn get(&self, var: &str) -> Option<f32>
+    {
+        match var
+        {
+            "master" => Some(self.master),
+            "music" => Some(self.music),
+            "sfx" => Some(self.sfx),
+            "mute" => Some(if self.muted { 1.0 } else { 0.0 }),
+            _ => None
+        }
+    }
+
+    /// Volumes get clamped to 0..1, mute is on for anything but 0
+    pub fn set(&mut self, var: &str, value: f32) -> Result<(), String>
+    {
+        match var
+        {
+            "master" => self.master = value.clamp(0.0, 1.0),
+            "music" => self.music = value.clamp(0.0, 1.0),
+            "sfx" => self.sfx = value.clamp(0.0, 1.0),
+            "mute" => self.muted = value != 0.0,
+            _ => return Err(format!("unknown volume '{var}'"))
+        }
+        Ok(())
+    }
+
+    /// Same `name = value` lines as the tuning config
+    pub fn parse(contents: &str) -> (Volumes, Vec<String>)
+    {
+        let mut volumes = Volumes::default();
+        let mut errors = Vec::new();
+        for (n, line) in contents.lines().enumerate()
+        {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty()
+            {
+                continue;
+            }
+
+            let Some((var, value)) = line.split_once('=') else {
+                errors.push(format!("{SETTINGS_FILE}:{} expected name = value", n + 1));
+                continue;
+            };
+            let result = value.trim().parse::<f32>()
+                .map_err(|e| format!("'{}' isn't a number ({e})", value.trim()))
+                .and_then(|value| volumes.set(var.trim(), value));
+            if let Err(err) = result
+            {
+                errors.push(format!("{SETTINGS_FILE}:{} {err}", n + 1));
+            }
+        }
+        (volumes, errors)
+    }
+
+    pub fn to_file_string(self) -> String
+    {
+        VOLUME_VARS.iter()
+            .filter_map(|var| self.get(var).map(|value| format!("{var} = {value}\n")))
+            .collect()
+    }
+}
+
+/// Extra layer on top of a scene's music, in once the intensity gets to its level
+struct Stem
+{
+    level: f32,
+    source: audio::Source,
+    mix: f32
+}
+
+/// A scene's music and how far it's faded in
+struct Track
+{
+    scene: Scene,
+    source: audio::Source,
+    fade: f32,
+    /// these start with the music and loop along with it, so they have to be the same length
+    stems: Vec<Stem>,
+    /// played on the music volume whenever an obstacle comes in
+    cue: Option<SoundPool>
+}
+
+impl Track
+{
+    fn new(scene: Scene, source: audio::Source) -> Track
+    {
+        Track { scene, source, fade: 0.0, stems: Vec::new(), cue: None }
+    }
+
+    fn play(&mut self, ctx: &mut Context) -> ggez::GameResult
+    {
+        self.source.play(ctx)?;
+        for stem in &mut self.stems
+        {
+            stem.source.play(ctx)?;
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self, ctx: &mut Context) -> ggez::GameResult
+    {
+        self.source.stop(ctx)?;
+        for stem in &mut self.stems
+        {
+            stem.source.stop(ctx)?;
+        }
+        Ok(())
+    }
+}
+
+/// Moves value towards target by at most step
+fn approach(value: f32, target: f32, step: f32) -> f32
+{
+    if value < target { (value + step).min(target) } else { (value - step).max(target) }
+}
+
+/// A set of clips to pick from and whichever of them are playing right now
+struct SoundPool
+{
+    clips: Vec<audio::SoundData>,
+    variation: Variation,
+    /// oldest first
+    voices: Vec<audio::Source>,
+    since_played: f32
+}
+
+impl SoundPool
+{
+    fn new(variation: Variation) -> SoundPool
+    {
+        SoundPool {
+            clips: Vec::new(),
+            variation,
+            voices: Vec::new(),
+            since_played: f32::INFINITY
+        }
+    }
+
+    fn play(&mut self, ctx: &mut Context, gain: f32) -> ggez::GameResult
+    {
+        self.voices.retain(|v| !v.stopped());
+        if self.clips.is_empty() || self.since_played < self.variation.cooldown
+        {
+            return Ok(());
+        }
+        if self.voices.len() >= self.variation.voices.max(1)
+        {
+            // dropping a Source stops it
+            self.voices.remove(0);
+        }
+
+        let mut rng = rand::thread_rng();
+        let clip = self.clips[rng.gen_range(0, self.clips.len())].clone();
+        let mut source = audio::Source::from_data(ctx, clip)?;
+        if self.variation.pitch > 0.0
+        {
+            source.set_pitch(1.0 + rng.gen_range(-self.variation.pitch, self.variation.pitch));
+        }
+        if self.variation.volume > 0.0
+        {
+            source.set_volume(gain * (1.0 - rng.gen_range(0.0, self.variation.volume)));
+        }
+        else
+        {
+            source.set_volume(gain);
+        }
+        source.play(ctx)?;
+
+        self.voices.push(source);
+        self.since_played = 0.0;
+        Ok(())
+    }
+}
+
+/// Plays everything that makes a noise, systems queue sound events and
+/// they get played in the next update() since that needs the Context
+#[derive(Default)]
+pub struct AudioManager
+{
+    pub volumes: Volumes,
+    sounds: Vec<(SoundEvent, SoundPool)>,
+    music: Vec<Track>,
+    scene: Option<Scene>,
+    /// how far into the game we are, in difficulty levels, decides which stems play
+    intensity: f32,
+    cue_pending: bool,
+    queued: Vec<SoundEvent>,
+    /// every clip audio.cfg names, loaded or not, so they can be watched for changes
+    clip_files: Vec<String>,
+    /// volumes changed since they were last saved
+    unsaved: bool,
+    settings_loaded: bool
+}
+
+impl AudioManager
+{
+    pub fn clip_files(&self) -> &[String]
+    {
+        &self.clip_files
+    }
+
+    /// Play a sound event with the next update, events without clips are dropped right away
+    pub fn trigger(&mut self, event: SoundEvent)
+    {
+        if self.sounds.iter().any(|(e, s)| *e == event && !s.clips.is_empty())
+        {
+            self.queued.push(event);
+        }
+    }
+
+    /// Switch the music over to the scene's, crossfading from whatever's playing
+    pub fn set_scene(&mut self, scene: Scene)
+    {
+        self.scene = Some(scene);
+    }
+
+    /// Stems with a level at or under this play, 0 is just the plain music
+    pub fn set_intensity(&mut self, intensity: f32)
+    {
+        self.intensity = intensity;
+    }
+
+    /// Play the current scene's cue with the next update, in time with an obstacle coming in
+    pub fn cue(&mut self)
+    {
+        self.cue_pending = true;
+    }
+
+    pub fn set_volume(&mut self, var: &str, value: f32) -> Result<(), String>
+    {
+        self.volumes.set(var, value)?;
+        self.unsaved = true;
+        Ok(())
+    }
+
+    pub fn toggle_mute(&mut self)
+    {
+        self.volumes.muted = !self.volumes.muted;
+        self.unsaved = true;
+    }
+
+    /// Play what's queued, move the crossfade along and save the volumes if they changed
+    pub fn update(&mut self, ctx: &mut Context, dt: f32) -> Vec<String>
+    {
+        let mut errors = Vec::new();
+
+        let gain = self.volumes.sfx_gain();
+        for (_, sounds) in &mut self.sounds
+        {
+            sounds.since_played += dt;
+        }
+        for event in std::mem::take(&mut self.queued)
+        {
+            let Some((_, sounds)) = self.sounds.iter_mut().find(|(e, _)| *e == event) else {
+                continue;
+            };
+            if gain <= 0.0
+            {
+                continue;
+            }
+            if let Err(e) = sounds.play(ctx, gain)
+            {
+                errors.push(format!("couldn't play {event:?}: {e}"));
+            }
+        }
+
+        let gain = self.volumes.music_gain();
+        let cue = std::mem::take(&mut self.cue_pending);
+        for track in &mut self.music
+        {
+            let current = self.scene == Some(track.scene);
+            track.fade = approach(track.fade, if current { 1.0 } else { 0.0 }, dt / CROSSFADE_TIME);
+            track.source.set_volume(track.fade * gain);
+            for stem in &mut track.stems
+            {
+                let on = current && self.intensity >= stem.level;
+                stem.mix = approach(stem.mix, if on { 1.0 } else { 0.0 }, dt / STEM_FADE_TIME);
+                stem.source.set_volume(track.fade * stem.mix * gain);
+            }
+
+            if let Some(pool) = &mut track.cue
+            {
+                pool.since_played += dt;
+                if cue && current && gain > 0.0
+                {
+                    if let Err(e) = pool.play(ctx, gain * track.fade)
+                    {
+                        errors.push(format!("couldn't play the {:?} cue: {e}", track.scene));
+                    }
+                }
+            }
+
+            let result = if track.fade > 0.0 && track.source.stopped()
+            {
+                track.play(ctx)
+            }
+            else if track.fade <= 0.0 && !track.source.stopped()
+            {
+                // faded all the way out, it starts from the top next time
+                track.stop(ctx)
+            }
+            else
+            {
+                Ok(())
+            };
+            if let Err(e) = result
+            {
+                errors.push(format!("couldn't play the {:?} music: {e}", track.scene));
+            }
+        }
+
+        if self.unsaved
+        {
+            self.unsaved = false;
+            if let Err(e) = save_settings(ctx, &self.volumes)
+            {
+                errors.push(format!("couldn't save {SETTINGS_FILE}: {e}"));
+            }
+        }
+        errors
+    }
+
+    fn sounds_mut(&mut self, event: SoundEvent) -> &mut SoundPool
+    {
+        match self.sounds.iter().position(|(e, _)| *e == event)
+        {
+            Some(i) => &mut self.sounds[i].1,
+            None => {
+                self.sounds.push((event, SoundPool::new(event.default_variation())));
+                &mut self.sounds.last_mut().unwrap().1
+            }
+        }
+    }
+
+    fn track_mut(&mut self, scene: Scene) -> Result<&mut Track, String>
+    {
+        self.music.iter_mut().find(|t| t.scene == scene)
+            .ok_or(format!("the music for {scene:?} has to come before its stems and cue"))
+    }
+
+    /// Parse the audio config and load every clip in it, clips that fail to load are skipped and reported back
+    fn load_clips(&mut self, ctx: &mut Context, contents: &str) -> Vec<String>
+    {
+        let mut errors = Vec::new();
+        self.sounds.clear();
+        self.music.clear();
+        self.clip_files.clear();
+
+        for (n, line) in contents.lines().enumerate()
+        {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty()
+            {
+                continue;
+            }
+
+            let Some((key, values)) = line.split_once('=') else {
+                errors.push(format!("{AUDIO_FILE}:{} expected name = clips", n + 1));
+                continue;
+            };
+            let key: Vec<&str> = key.split_whitespace().collect();
+            if let Err(err) = self.parse_line(ctx, &key, values)
+            {
+                errors.push(format!("{AUDIO_FILE}:{} {err}", n + 1));
+            }
+        }
+        errors
+    }
+
+    fn parse_line(&mut self, ctx: &mut Context, key: &[&str], values: &str) -> Result<(), String>
+    {
+        let values: Vec<&str> = values.split_whitespace().collect();
+        let event = |name: &str| SoundEvent::parse(name).ok_or(format!("unknown sound event '{name}'"));
+        let num = |value: &str| value.parse::<f32>().map_err(|e| format!("'{value}' isn't a number ({e})"));
+
+        // vary and voices take numbers, everything else takes clips
+        if !matches!(key, ["vary", _] | ["voices", _])
+        {
+            self.clip_files.extend(values.iter().map(|v| v.to_string()));
+        }
+        match key
+        {
+            ["music", scene] => {
+                let scene = Scene::parse(scene).ok_or(format!("unknown scene '{scene}'"))?;
+                let source = load_loop(ctx, values.first())?;
+                self.music.retain(|t| t.scene != scene);
+                self.music.push(Track::new(scene, source));
+            },
+            ["stem", scene, level] => {
+                let scene = Scene::parse(scene).ok_or(format!("unknown scene '{scene}'"))?;
+                let level = num(level)?;
+                let source = load_loop(ctx, values.first())?;
+                self.track_mut(scene)?.stems.push(Stem { level, source, mix: 0.0 });
+            },
+            ["cue", scene] => {
+                let scene = Scene::parse(scene).ok_or(format!("unknown scene '{scene}'"))?;
+                let file = values.first().ok_or("cue needs a clip")?;
+                let data = audio::SoundData::new(ctx, file).map_err(|e| format!("couldn't load {file}: {e}"))?;
+                let mut pool = SoundPool::new(Variation { pitch: 0.0, volume: 0.0, voices: 2, cooldown: 0.1 });
+                pool.clips.push(data);
+                self.track_mut(scene)?.cue = Some(pool);
+            },
+            ["vary", name] => {
+                let [pitch, volume] = values.as_slice() else {
+                    return Err("vary needs a pitch and a volume".to_string());
+                };
+                let variation = &mut self.sounds_mut(event(name)?).variation;
+                variation.pitch = num(pitch)?.clamp(0.0, 0.9);
+                variation.volume = num(volume)?.clamp(0.0, 1.0);
+            },
+            ["voices", name] => {
+                let [count] = values.as_slice() else {
+                    return Err("voices needs a count".to_string());
+                };
+                let count = count.parse::<usize>().map_err(|e| format!("'{count}' isn't a count ({e})"))?;
+                self.sounds_mut(event(name)?).variation.voices = count.max(1);
+            },
+            [name] => {
+                let event = event(name)?;
+                // load everything first so one bad clip doesn't take the good ones with it
+                let mut clips = Vec::new();
+                let mut bad = Vec::new();
+                for file in values
+                {
+                    match audio::SoundData::new(ctx, file)
+                    {
+                        Ok(data) if data.can_play() => clips.push(data),
+                        Ok(_) => bad.push(format!("{file} isn't a sound ggez can play")),
+                        Err(e) => bad.push(format!("couldn't load {file}: {e}"))
+                    }
+                }
+                self.sounds_mut(event).clips = clips;
+                if !bad.is_empty()
+                {
+                    return Err(bad.join(", "));
+                }
+            },
+            _ => return Err("expected a sound event, vary, voices, music, stem or cue".to_string())
+        }
+        Ok(())
+    }
+}
+
+/// A music clip that loops
+fn load_loop(ctx: &mut Context, file: Option<&&str>) -> Result<audio::Source, String>
+{
+    let file = file.ok_or("music needs a clip")?;
+    let mut source = audio::Source::new(ctx, file).map_err(|e| format!("couldn't load {file}: {e}"))?;
+    source.set_repeat(true);
+    Ok(source)
+}
+
+fn save_settings(ctx: &mut Context, volumes: &Volumes) -> ggez::GameResult
+{
+    let mut file = filesystem::create(ctx, SETTINGS_FILE)?;
+    file.write_all(volumes.to_file_string().as_bytes())?;
+    Ok(())
+}
+
+/// (Re)load the audio config, the volumes come from the settings the first time and are kept after that
+pub fn load(ctx: &mut Context, world: &mut World) -> Vec<String>
+{
+    let mut errors = Vec::new();
+    let mut manager = world.remove::<AudioManager>().unwrap_or_default();
+
+    if !manager.settings_loaded
+    {
+        match read_asset(ctx, SETTINGS_FILE)
+        {
+            Ok(Some(contents)) => {
+                let (volumes, settings_errors) = Volumes::parse(&contents);
+                manager.volumes = volumes;
+                errors.extend(settings_errors);
+            },
+            Ok(None) => {},
+            Err(e) => errors.push(e)
+        }
+        manager.settings_loaded = true;
+    }
+
+    // whatever was playing is replaced, the crossfade brings the new music back in
+    for track in &mut manager.music
+    {
+        if let Err(e) = track.stop(ctx)
+        {
+            errors.push(format!("couldn't stop the {:?} music: {e}", track.scene));
+        }
+    }
+    match read_asset(ctx, AUDIO_FILE)
+    {
+        Ok(Some(contents)) => errors.extend(manager.load_clips(ctx, &contents)),
+        Ok(None) => errors.push(format!("no {AUDIO_FILE}, there won't be any sound")),
+        Err(e) => errors.push(e)
+    }
+
+    world.insert(manager);
+    errors
+}
@@ -0,0 +1,257 @@
+use specs::*;
+
+use crate::*;
+use crate::behaviours::{self, Motions};
+use crate::course::{Course, Gap, Physics};
+use crate::patterns::{PatternLibrary, MIN_SPACING};
+use crate::triggers::{Trigger, TriggerKind};
+
+/// Horizontal distance between two pairs following each other
+pub const OBSTACLE_SPACING:f32 = WINDOW_W/3.0;
+/// Where idle pairs wait, just off the right edge
+pub const SPAWN_X:f32 = WINDOW_W+50.0;
+/// How far past the left edge a pair goes before it's recycled
+pub const DESPAWN_MARGIN:f32 = 50.0;
+
+const OBSTACLE_WIDTH:f32 = 2.0*SQUARE_SIZE/3.0;
+
+/// A pipe pair, the top and bottom hitboxes are separate entities derived from this
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct ObstaclePair
+{
+    pub pos_x: f32,
+    pub gap_center: f32,
+    pub gap_size: f32,
+    pub width: f32
+}
+impl ObstaclePair
+{
+    pub fn new(pos_x: f32, gap_center: f32) -> ObstaclePair
+    {
+        ObstaclePair {
+            pos_x,
+            gap_center,
+            gap_size: OBSTACLE_TIGTHFACTOR,
+            width: OBSTACLE_WIDTH
+        }
+    }
+
+    pub fn hitbox(&self, part: ObstaclePart) -> Rect
+    {
+        match part
+        {
+            ObstaclePart::Top => Rect {
+                pos_x: self.pos_x, pos_y: 0.0,
+                size_x: self.width, size_y: self.gap_center - self.gap_size/2.0
+            },
+            ObstaclePart::Bottom => Rect {
+                pos_x: self.pos_x, pos_y: self.gap_center + self.gap_size/2.0,
+                size_x: self.width, size_y: WINDOW_H - (self.gap_center + self.gap_size/2.0)
+            }
+        }
+    }
+
+    /// The open space between the two halves
+    pub fn gap_rect(&self) -> Rect
+    {
+        Rect {
+            pos_x: self.pos_x, pos_y: self.gap_center - self.gap_size/2.0,
+            size_x: self.width, size_y: self.gap_size
+        }
+    }
+
+    pub fn is_off_screen(&self) -> bool
+    {
+        self.pos_x + self.width + DESPAWN_MARGIN < 0.0
+    }
+}
+
+/// Where a pair is in its life in the pool
+/// Idle pairs wait at SPAWN_X, Active ones move towards the player and
+/// become Passed once the player is by them, until they go off screen and are Idle again
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+#[storage(VecStorage)]
+pub enum ObstacleState
+{
+    Idle,
+    Active,
+    Passed
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObstaclePart
+{
+    Top,
+    Bottom
+}
+
+/// Top or bottom half of an ObstaclePair, its Rect gets laid out from the pair every tick
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct ObstacleHitbox
+{
+    pub pair: Entity,
+    pub part: ObstaclePart
+}
+
+/// Score trigger sitting in the gap of an ObstaclePair, laid out along with the hitboxes
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct ObstacleGate
+{
+    pub pair: Entity
+}
+
+/// Size of the obstacle pool, enough pairs to cover the screen at the tightest pattern spacing
+#[derive(Clone, Copy, Debug)]
+pub struct ObstaclePool
+{
+    pub capacity: usize,
+    pub spacing: f32
+}
+
+impl Default for ObstaclePool
+{
+    fn default() -> Self
+    {
+        ObstaclePool::new(OBSTACLE_SPACING)
+    }
+}
+
+impl ObstaclePool
+{
+    pub fn new(spacing: f32) -> ObstaclePool
+    {
+        // everything from the spawn point to fully off the left edge can be on screen at once,
+        // plus one spare waiting to come in
+        let span = SPAWN_X + OBSTACLE_WIDTH + DESPAWN_MARGIN;
+        ObstaclePool {
+            capacity: (span / (spacing * MIN_SPACING)).ceil() as usize + 1,
+            spacing
+        }
+    }
+}
+
+/// Builds a pair entity plus the two hitbox entities and the score trigger hanging off it
+fn create_obstacle_pair(world: &mut World, pair: ObstaclePair, speed: f32) -> Entity
+{
+    let top = pair.hitbox(ObstaclePart::Top);
+    let bottom = pair.hitbox(ObstaclePart::Bottom);
+    let gap = pair.gap_rect();
+
+    let pair = world
+        .create_entity()
+        .with(pair)
+        .with(ObstacleState::Idle)
+        .with(Velocity { x: speed, y: 0.0 })
+        .build();
+
+    world
+        .create_entity()
+        .with(top)
+        .with(Collision)
+        .with(ObstacleHitbox { pair, part: ObstaclePart::Top })
+        .build();
+    world
+        .create_entity()
+        .with(bottom)
+        .with(Collision)
+        .with(ObstacleHitbox { pair, part: ObstaclePart::Bottom })
+        .build();
+    world
+        .create_entity()
+        .with(gap)
+        .with(Trigger::new(TriggerKind::Score))
+        .with(ObstacleGate { pair })
+        .build();
+    pair
+}
+
+/// Fill the pool with idle pairs, ObstacleSysten sends them in from there
+pub fn create_pool(world: &mut World, pool: ObstaclePool)
+{
+    let speed = world.read_resource::<ObstacleSpeed>().0;
+    for _ in 0..pool.capacity
+    {
+        // the gap gets rolled when the pair is sent in
+        create_obstacle_pair(world, ObstaclePair::new(SPAWN_X, WINDOW_H/2.0), speed);
+    }
+    world.insert(pool);
+    world.insert(Course::default());
+}
+
+/// Everything hanging off a pair that has to go along when it jumps in or out of the pool
+pub type PairParts<'a> = (Motions<'a>, ReadStorage<'a, ObstacleHitbox>, WriteStorage<'a, LastRect>);
+
+/// Put a pair back in the pool, without the motion it had
+pub fn recycle(entity: Entity, pair: &mut ObstaclePair, state: &mut ObstacleState, parts: &mut PairParts)
+{
+    pair.pos_x = SPAWN_X;
+    *state = ObstacleState::Idle;
+    behaviours::detach(&mut parts.0, entity);
+    reset_sweep(entity, pair, parts);
+}
+
+/// Send a pair in at pos_x with a gap and that gap's motion, from the course or a level
+pub fn place(entity: Entity, pair: &mut ObstaclePair, state: &mut ObstacleState, pos_x: f32, gap: &Gap, parts: &mut PairParts)
+{
+    pair.pos_x = pos_x;
+    pair.gap_center = gap.center;
+    pair.gap_size = gap.size;
+    *state = ObstacleState::Active;
+    behaviours::attach(&mut parts.0, entity, pair, gap);
+    reset_sweep(entity, pair, parts);
+}
+
+/// The pair jumped instead of moving, so its hitboxes start this tick where they are now
+/// and the collision sweep doesn't drag them across the whole screen
+fn reset_sweep(entity: Entity, pair: &ObstaclePair, parts: &mut PairParts)
+{
+    let (_, hitboxes, last) = parts;
+    for (last, hitbox) in (&mut *last, &*hitboxes).join()
+    {
+        if hitbox.pair == entity
+        {
+            last.0 = pair.hitbox(hitbox.part);
+        }
+    }
+}
+
+/// Every pair goes back to idle and the course starts over, like the game was just started
+pub fn reset_pool(world: &mut World)
+{
+    let entities = world.entities();
+    let mut pairs = world.write_storage::<ObstaclePair>();
+    let mut states = world.write_storage::<ObstacleState>();
+    let mut parts = world.system_data::<PairParts>();
+
+    for (entity, pair, state) in (&entities, &mut pairs, &mut states).join()
+    {
+        recycle(entity, pair, state, &mut parts);
+    }
+    world.write_resource::<Course>().reset();
+}
+
+/// Send in the next idle pair, returns false if the whole pool is in use
+pub fn spawn_next(world: &mut World) -> bool
+{
+    let physics = Physics::from_world(world);
+    let library = world.read_resource::<PatternLibrary>();
+    let difficulty = world.read_resource::<Difficulty>().0;
+    let mut course = world.write_resource::<Course>();
+    let entities = world.entities();
+    let mut pairs = world.write_storage::<ObstaclePair>();
+    let mut states = world.write_storage::<ObstacleState>();
+    let mut parts = world.system_data::<PairParts>();
+
+    match (&entities, &mut pairs, &mut states).join().find(|(_, _, state)| **state == ObstacleState::Idle)
+    {
+        Some((entity, pair, state)) => {
+            let gap = course.next_gap(&library, difficulty, pair.width, &physics);
+            place(entity, pair, state, SPAWN_X, &gap, &mut parts);
+            true
+        },
+        None => false
+    }
+}
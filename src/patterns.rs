@@ -0,0 +1,255 @@
+use ggez::Context;
+use rand::*;
+use specs::*;
+
+use crate::*;
+use crate::behaviours::Motion;
+
+/// Obstacle patterns, see the comments at the top of the file for the format
+pub const PATTERN_FILE:&str = "/patterns.cfg";
+
+/// Pairs can't get closer together than this times the normal spacing
+pub const MIN_SPACING:f32 = 0.5;
+
+/// One pair of a pattern
+#[derive(Clone, Copy, Debug)]
+pub struct Step
+{
+    /// gap height change from the pair before, None rolls a random height
+    pub offset: Option<f32>,
+    pub gap_size: f32,
+    /// distance from the pair before as a multiple of the normal spacing
+    pub spacing: f32,
+    pub motion: Motion
+}
+
+impl Default for Step
+{
+    fn default() -> Self
+    {
+        Step {
+            offset: None,
+            gap_size: OBSTACLE_TIGTHFACTOR,
+            spacing: 1.0,
+            motion: Motion::Still
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Pattern
+{
+    pub name: String,
+    pub difficulty: u8,
+    pub weight: f32,
+    pub growth: f32,
+    pub steps: Vec<Step>
+}
+
+impl Pattern
+{
+    fn new(name: &str) -> Pattern
+    {
+        Pattern {
+            name: name.to_string(),
+            difficulty: 0,
+            weight: 1.0,
+            growth: 0.0,
+            steps: vec![Step::default()]
+        }
+    }
+
+    /// How likely this pattern is at a difficulty, 0 if it's not unlocked yet
+    pub fn weight_at(&self, difficulty: u8) -> f32
+    {
+        if difficulty < self.difficulty
+        {
+            return 0.0;
+        }
+        (self.weight + self.growth * (difficulty - self.difficulty) as f32).max(0.0)
+    }
+}
+
+/// Every pattern loaded from PATTERN_FILE
+#[derive(Clone, Debug, Default)]
+pub struct PatternLibrary
+{
+    pub patterns: Vec<Pattern>
+}
+
+impl PatternLibrary
+{
+    /// Parse the pattern file, bad lines are skipped and reported back
+    pub fn parse(contents: &str) -> (PatternLibrary, Vec<String>)
+    {
+        let mut library = PatternLibrary::default();
+        let mut errors = Vec::new();
+        // gap, spacing and motion apply to every step, but offsets can come before them
+        let mut gap_size = None;
+        let mut spacing = None;
+        let mut motion = None;
+
+        for (n, line) in contents.lines().enumerate()
+        {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty()
+            {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']'))
+            {
+                library.finish_pattern(gap_size.take(), spacing.take(), motion.take());
+                library.patterns.push(Pattern::new(name.trim()));
+                continue;
+            }
+
+            let Some(pattern) = library.patterns.last_mut() else {
+                errors.push(format!("{PATTERN_FILE}:{} expected a [pattern] first", n + 1));
+                continue;
+            };
+            let Some((key, value)) = line.split_once('=') else {
+                errors.push(format!("{PATTERN_FILE}:{} expected name = value", n + 1));
+                continue;
+            };
+
+            let (key, value) = (key.trim(), value.trim());
+            let result = match key
+            {
+                "difficulty" => value.parse().map(|v| pattern.difficulty = v).map_err(|e| e.to_string()),
+                "weight" => value.parse().map(|v| pattern.weight = v).map_err(|e| e.to_string()),
+                "growth" => value.parse().map(|v| pattern.growth = v).map_err(|e| e.to_string()),
+                "gap" => value.parse().map(|v| gap_size = Some(v)).map_err(|e| e.to_string()),
+                "spacing" => value.parse().map(|v| spacing = Some(v)).map_err(|e| e.to_string()),
+                "motion" => Motion::parse(value).map(|v| motion = Some(v)),
+                "offsets" => parse_offsets(value).map(|steps| pattern.steps = steps),
+                _ => Err(format!("unknown key '{key}'"))
+            };
+            if let Err(err) = result
+            {
+                errors.push(format!("{PATTERN_FILE}:{} {err}", n + 1));
+            }
+        }
+        library.finish_pattern(gap_size, spacing, motion);
+
+        (library, errors)
+    }
+
+    fn finish_pattern(&mut self, gap_size: Option<f32>, spacing: Option<f32>, motion: Option<Motion>)
+    {
+        if let Some(pattern) = self.patterns.last_mut()
+        {
+            for step in &mut pattern.steps
+            {
+                step.gap_size = gap_size.unwrap_or(step.gap_size);
+                step.spacing = spacing.unwrap_or(step.spacing).max(MIN_SPACING);
+                step.motion = motion.unwrap_or(step.motion);
+            }
+        }
+    }
+
+    /// Weighted pick of the patterns unlocked at this difficulty
+    pub fn pick(&self, difficulty: u8, rng: &mut impl Rng) -> Option<&Pattern>
+    {
+        let total: f32 = self.patterns.iter().map(|p| p.weight_at(difficulty)).sum();
+        if total <= 0.0
+        {
+            return None;
+        }
+
+        let mut roll = rng.gen_range::<f32, f32, f32>(0.0, total);
+        for pattern in &self.patterns
+        {
+            roll -= pattern.weight_at(difficulty);
+            if roll < 0.0
+            {
+                return Some(pattern);
+            }
+        }
+        self.patterns.iter().rev().find(|p| p.weight_at(difficulty) > 0.0)
+    }
+}
+
+fn parse_offsets(value: &str) -> Result<Vec<Step>, String>
+{
+    let steps = value.split_whitespace()
+        .map(|offset| match offset
+        {
+            "?" => Ok(Step::default()),
+            _ => offset.parse::<f32>()
+                .map(|o| Step { offset: Some(o), ..Step::default() })
+                .map_err(|e| format!("'{offset}' isn't an offset ({e})"))
+        })
+        .collect::<Result<Vec<Step>, String>>()?;
+
+    if steps.is_empty()
+    {
+        return Err("a pattern needs at least one offset".to_string());
+    }
+    Ok(steps)
+}
+
+/// Load the patterns through the ggez filesystem, without the file every pair is just random
+pub fn load(ctx: &mut Context, world: &mut World) -> Vec<String>
+{
+    let contents = match read_asset(ctx, PATTERN_FILE)
+    {
+        Ok(Some(contents)) => contents,
+        Ok(None) => return Vec::new(),
+        Err(err) => return vec![err]
+    };
+
+    let (library, errors) = PatternLibrary::parse(&contents);
+    world.insert(library);
+    errors
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn parse()
+    {
+        let file = "# comment\n\
+            [stairs]\n\
+            offsets = 0 -40 ? 40\n\
+            gap = 180 # tight\n\
+            spacing = 0.1\n\
+            difficulty = 2\n\
+            growth = 0.5\n\
+            [wave]\n\
+            motion = oscillate 30 2\n\
+            weight = 3\n";
+        let (library, errors) = PatternLibrary::parse(file);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(library.patterns.len(), 2);
+
+        let stairs = &library.patterns[0];
+        assert_eq!(stairs.name, "stairs");
+        let offsets: Vec<Option<f32>> = stairs.steps.iter().map(|s| s.offset).collect();
+        assert_eq!(offsets, vec![Some(0.0), Some(-40.0), None, Some(40.0)]);
+        // gap and spacing apply to every step, whichever order they came in, and spacing has a floor
+        assert!(stairs.steps.iter().all(|s| s.gap_size == 180.0 && s.spacing == MIN_SPACING && s.motion == Motion::Still));
+        assert_eq!(stairs.weight_at(1), 0.0);
+        assert_eq!(stairs.weight_at(4), 2.0);
+
+        let wave = &library.patterns[1];
+        assert_eq!(wave.steps.len(), 1);
+        assert_eq!(wave.steps[0].gap_size, OBSTACLE_TIGTHFACTOR);
+        assert_eq!(wave.steps[0].motion, Motion::Oscillate { amplitude: 30.0, period: 2.0 });
+        assert_eq!(wave.weight_at(0), 3.0);
+    }
+
+    #[test]
+    fn bad_lines()
+    {
+        let file = "weight = 1\n[a]\nweight\ncolour = red\noffsets =\noffsets = 1 two\nmotion = spin\n";
+        let (library, errors) = PatternLibrary::parse(file);
+        assert_eq!(library.patterns.len(), 1);
+        assert_eq!(library.patterns[0].steps.len(), 1);
+        let lines: Vec<&str> = errors.iter().map(|e| e.split(' ').next().unwrap()).collect();
+        assert_eq!(lines, ["/patterns.cfg:1", "/patterns.cfg:3", "/patterns.cfg:4", "/patterns.cfg:5", "/patterns.cfg:6", "/patterns.cfg:7"]);
+    }
+}
@@ -0,0 +1,314 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use specs::*;
+
+use crate::*;
+use crate::autopilot::Observation;
+use crate::course::{Physics, NOMINAL_DT};
+use crate::gym::{Env, EnvConfig, Feature, DEFAULT_FEATURES};
+use crate::obstacles::{ObstaclePair, ObstacleState};
+
+pub const DEFAULT_PORT:u16 = 7878;
+/// How long the window waits on a lockstep bot before it lets a frame go by, so it keeps
+/// handling events. Nothing moves until the bot answers either way
+pub const LOCKSTEP_WAIT:Duration = Duration::from_millis(15);
+
+/// Whether the game waits for the bot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode
+{
+    /// every tick waits for the bot to say flap or wait
+    Lockstep,
+    /// the game runs at its own pace and takes whatever's come in by each tick
+    Realtime
+}
+
+impl Mode
+{
+    fn name(&self) -> &'static str
+    {
+        match self
+        {
+            Mode::Lockstep => "lockstep",
+            Mode::Realtime => "realtime"
+        }
+    }
+}
+
+/// One line from the bot, `{"cmd": "flap"}`, `{"cmd": "wait"}`, `{"cmd": "reset", "seed": 1}` or `{"cmd": "quit"}`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command
+{
+    Flap,
+    /// don't flap this tick, only means something in lockstep
+    Wait,
+    /// start a run, the seed's optional and decides the whole course
+    Reset(Option<u64>),
+    Quit
+}
+
+impl Command
+{
+    pub fn parse(line: &str) -> Result<Command, String>
+    {
+        let msg: Value = serde_json::from_str(line).map_err(|e| format!("not json: {e}"))?;
+        match msg["cmd"].as_str()
+        {
+            Some("flap") => Ok(Command::Flap),
+            Some("wait") => Ok(Command::Wait),
+            Some("reset") => Ok(Command::Reset(msg["seed"].as_u64())),
+            Some("quit") => Ok(Command::Quit),
+            Some(cmd) => Err(format!("unknown cmd '{cmd}', try flap, wait, reset or quit")),
+            None => Err("expected an object with a cmd".to_string())
+        }
+    }
+}
+
+/// What came in from the bot
+pub enum Incoming
+{
+    Command(Command),
+    /// nothing came in within the wait
+    Nothing,
+    /// the bot hung up
+    Gone
+}
+
+/// Where the game is with the bot once it's taken in what was sent
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Turn
+{
+    /// go ahead and tick
+    Ready,
+    /// lockstep and the bot hasn't decided this tick yet, don't tick
+    Waiting,
+    Gone
+}
+
+/// Line delimited JSON to and from a bot, over stdin/stdout or a socket on localhost
+pub struct Link
+{
+    pub mode: Mode,
+    commands: Receiver<Result<Command, String>>,
+    out: Box<dyn Write + Send>,
+    features: Vec<Feature>,
+    tick: u64
+}
+
+impl Link
+{
+    fn new(mode: Mode, input: impl Read + Send + 'static, out: Box<dyn Write + Send>) -> Link
+    {
+        // reading blocks, so it gets a thread of its own and the game just checks the channel
+        let (send, commands) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(input).lines()
+            {
+                let Ok(line) = line else {
+                    break;
+                };
+                if line.trim().is_empty()
+                {
+                    continue;
+                }
+                if send.send(Command::parse(&line)).is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        let features = Feature::parse_all(DEFAULT_FEATURES).expect("the default features parse");
+        Link { mode, commands, out, features, tick: 0 }
+    }
+
+    pub fn stdio(mode: Mode) -> Link
+    {
+        Link::new(mode, std::io::stdin(), Box::new(std::io::stdout()))
+    }
+
+    /// Waits for one bot to connect on 127.0.0.1
+    pub fn tcp(mode: Mode, port: u16) -> std::io::Result<Link>
+    {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        eprintln!("waiting for a bot on 127.0.0.1:{port}");
+        let (stream, addr) = listener.accept()?;
+        eprintln!("bot connected from {addr}");
+        stream.set_nodelay(true)?;
+        Ok(Link::new(mode, stream.try_clone()?, Box::new(stream)))
+    }
+
+    /// `stdio|tcp[:port] [lockstep|realtime] [headless]`, the bool is whether it's headless
+    pub fn from_args(args: &[String]) -> Result<(Link, bool), String>
+    {
+        let mut mode = Mode::Lockstep;
+        let mut headless = false;
+        for arg in args.iter().skip(1)
+        {
+            match arg.as_str()
+            {
+                "lockstep" => mode = Mode::Lockstep,
+                "realtime" => mode = Mode::Realtime,
+                "headless" => headless = true,
+                arg => return Err(format!("unknown option '{arg}', try lockstep, realtime or headless"))
+            }
+        }
+
+        let link = match args.first().map(String::as_str)
+        {
+            Some("stdio") => Link::stdio(mode),
+            Some(tcp) if tcp.starts_with("tcp") => {
+                let port = match tcp.strip_prefix("tcp:")
+                {
+                    Some(port) => port.parse().map_err(|e| format!("'{port}' isn't a port ({e})"))?,
+                    None => DEFAULT_PORT
+                };
+                Link::tcp(mode, port).map_err(|e| format!("couldn't listen on {port}: {e}"))?
+            },
+            _ => return Err("expected stdio or tcp[:port]".to_string())
+        };
+        Ok((link, headless))
+    }
+
+    /// The next command, waiting up to wait for one, Duration::MAX waits for good.
+    /// Lines that didn't parse get an error sent back and are skipped
+    pub fn receive(&mut self, wait: Duration) -> Incoming
+    {
+        loop
+        {
+            match self.commands.recv_timeout(wait)
+            {
+                Ok(Ok(cmd)) => return Incoming::Command(cmd),
+                Ok(Err(err)) => self.error(&err),
+                Err(RecvTimeoutError::Timeout) => return Incoming::Nothing,
+                Err(RecvTimeoutError::Disconnected) => return Incoming::Gone
+            }
+        }
+    }
+
+    fn send(&mut self, msg: &Value)
+    {
+        // a bot that's gone shows up on the reading side, nothing to do about it here
+        let _ = writeln!(self.out, "{msg}").and_then(|_| self.out.flush());
+    }
+
+    pub fn error(&mut self, message: &str)
+    {
+        self.send(&json!({ "type": "error", "message": message }));
+    }
+
+    /// First thing the bot gets, what to expect from the rest
+    pub fn hello(&mut self)
+    {
+        let features: Vec<&str> = DEFAULT_FEATURES.split_whitespace().collect();
+        self.send(&json!({
+            "type": "hello",
+            "mode": self.mode.name(),
+            "width": WINDOW_W,
+            "height": WINDOW_H,
+            "features": features,
+            "commands": ["flap", "wait", "reset", "quit"]
+        }));
+    }
+
+    /// Start counting ticks over for a new run
+    pub fn restart(&mut self)
+    {
+        self.tick = 0;
+    }
+
+    /// Where the bird and the gaps are right now, sent after every tick and after a reset
+    pub fn send_state(&mut self, world: &World, reward: Option<f32>)
+    {
+        let rect = world.read_storage::<Rect>();
+        let velo = world.read_storage::<Velocity>();
+        let control = world.read_storage::<Controllable>();
+        let pairs = world.read_storage::<ObstaclePair>();
+        let states = world.read_storage::<ObstacleState>();
+        let Some((r, vel, _)) = (&rect, &velo, &control).join().next() else {
+            return;
+        };
+        let obs = Observation::new(r, vel.y, (&pairs, &states).join(), Physics::from_world(world));
+
+        let gaps: Vec<Value> = obs.gaps.iter()
+            .map(|g| json!({ "x": g.x, "width": g.width, "top": g.top, "bottom": g.bottom }))
+            .collect();
+        let features: Vec<f32> = self.features.iter().map(|f| f.value(&obs)).collect();
+        let mut msg = json!({
+            "type": "state",
+            "tick": self.tick,
+            "score": world.read_resource::<Score>().0,
+            "done": world.read_resource::<IsGameover>().0,
+            "bird": { "x": r.pos_x, "y": r.pos_y, "width": r.size_x, "height": r.size_y, "vel_y": vel.y },
+            "gaps": gaps,
+            "obs": features
+        });
+        if let Some(reward) = reward
+        {
+            msg["reward"] = json!(reward);
+        }
+        self.send(&msg);
+        self.tick += 1;
+    }
+}
+
+/// Let a bot play on a gym env without a window. Nothing runs until it sends a reset
+pub fn serve_headless(mut link: Link, asset_dir: &Path)
+{
+    let mut env = Env::new(EnvConfig::default());
+    // stdout might be the bot's, so anything for a person goes to stderr
+    for err in env.load_assets(asset_dir)
+    {
+        eprintln!("{err}");
+    }
+    link.hello();
+
+    let frame = Duration::from_secs_f32(NOMINAL_DT);
+    let mut next_frame = Instant::now();
+    let mut done = true;
+    let mut flap = false;
+    loop
+    {
+        let lockstep = link.mode == Mode::Lockstep;
+        // in lockstep the next command is what happens this tick, realtime takes everything that's come in
+        let wait = if lockstep || done { Duration::MAX } else { Duration::ZERO };
+        let step = match link.receive(wait)
+        {
+            Incoming::Gone | Incoming::Command(Command::Quit) => return,
+            Incoming::Command(Command::Reset(seed)) => {
+                env.reset(seed.unwrap_or_else(rand::random));
+                done = false;
+                link.restart();
+                link.send_state(env.world_mut(), Some(0.0));
+                next_frame = Instant::now() + frame;
+                false
+            },
+            Incoming::Command(Command::Flap | Command::Wait) if done => {
+                link.error("the run's over, send reset");
+                false
+            },
+            Incoming::Command(cmd) => {
+                flap |= cmd == Command::Flap;
+                lockstep
+            },
+            Incoming::Nothing => Instant::now() >= next_frame
+        };
+
+        if step && !done
+        {
+            let (_, reward, over) = env.step(std::mem::take(&mut flap));
+            done = over;
+            link.send_state(env.world_mut(), Some(reward));
+            next_frame += frame;
+        }
+        if !lockstep && !done
+        {
+            std::thread::sleep(next_frame.saturating_duration_since(Instant::now()).min(Duration::from_millis(1)));
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use std::fmt;
+
+use glam::Vec2;
+
+use crate::*;
+
+/// How long a popup floats up before it's gone, in seconds
+pub const POPUP_TIME:f32 = 0.8;
+/// How far a popup floats up over its lifetime
+const POPUP_RISE:f32 = 60.0;
+/// Popups scored in the same tick go on top of each other this far apart
+const POPUP_STACK:f32 = 45.0;
+
+/// Passing with less room than this to an edge of the gap is a near miss,
+/// the bird's Rect is a bit bigger than its collider so this can go negative without a hit
+pub const NEAR_MISS_DIST:f32 = 12.0;
+/// How far off the middle of the gap still counts as a centre pass, as a fraction of half the gap
+pub const CENTRE_TOLERANCE:f32 = 0.15;
+/// Every this many centre passes in a row the combo multiplier goes up by one
+pub const COMBO_STEP:u32 = 3;
+
+/// Why points were scored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScoreReason
+{
+    Pipe,
+    NearMiss,
+    CentrePass
+}
+
+impl ScoreReason
+{
+    /// Points before any multipliers
+    pub fn base_points(&self) -> u64
+    {
+        match self
+        {
+            ScoreReason::Pipe => 1,
+            ScoreReason::NearMiss => 2,
+            ScoreReason::CentrePass => 1
+        }
+    }
+}
+
+impl fmt::Display for ScoreReason
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            ScoreReason::Pipe => write!(f, "pipe"),
+            ScoreReason::NearMiss => write!(f, "near miss"),
+            ScoreReason::CentrePass => write!(f, "centre")
+        }
+    }
+}
+
+/// How cleanly the bird went into a gap
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PassStyle
+{
+    /// brushed past an edge
+    NearMiss,
+    /// right down the middle
+    Centre,
+    Sloppy
+}
+
+impl PassStyle
+{
+    /// Judge the bird's Rect against the gap's at the moment it goes in.
+    /// Going in partly inside a pipe is sloppy, not a near miss
+    pub fn judge(bird: &Rect, gap: &Rect) -> PassStyle
+    {
+        let clearance = (bird.pos_y - gap.pos_y).min(gap.pos_y + gap.size_y - (bird.pos_y + bird.size_y));
+        let off_centre = ((bird.pos_y + bird.size_y/2.0) - (gap.pos_y + gap.size_y/2.0)).abs();
+
+        if clearance < 0.0
+        {
+            PassStyle::Sloppy
+        }
+        else if clearance < NEAR_MISS_DIST
+        {
+            PassStyle::NearMiss
+        }
+        else if off_centre <= CENTRE_TOLERANCE * gap.size_y/2.0
+        {
+            PassStyle::Centre
+        }
+        else
+        {
+            PassStyle::Sloppy
+        }
+    }
+}
+
+/// Centre passes in a row, a sloppy pass breaks it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Combo
+{
+    pub count: u32,
+    pub best: u32
+}
+
+impl Combo
+{
+    /// Count the pass, near misses keep the combo going without adding to it
+    pub fn pass(&mut self, style: PassStyle)
+    {
+        match style
+        {
+            PassStyle::Centre => {
+                self.count += 1;
+                self.best = self.best.max(self.count);
+            },
+            PassStyle::NearMiss => {},
+            PassStyle::Sloppy => self.count = 0
+        }
+    }
+
+    /// What the combo multiplies points by
+    pub fn factor(&self) -> u64
+    {
+        1 + (self.count / COMBO_STEP) as u64
+    }
+}
+
+/// Something worth points, gameplay systems queue these and ScoreSystem works out what they're worth
+#[derive(Clone, Copy, Debug)]
+pub struct ScoreClaim
+{
+    pub reason: ScoreReason,
+    /// where on screen it happened
+    pub pos: Vec2
+}
+
+/// Claims waiting for ScoreSystem, emptied every tick
+#[derive(Clone, Debug, Default)]
+pub struct ScoreClaims(pub Vec<ScoreClaim>);
+
+impl ScoreClaims
+{
+    pub fn push(&mut self, reason: ScoreReason, pos: Vec2)
+    {
+        self.0.push(ScoreClaim { reason, pos });
+    }
+}
+
+/// Scales everything scored while it's there
+#[derive(Clone, Copy, Debug)]
+pub struct Multiplier
+{
+    /// whoever set it, so it can be changed or taken back
+    pub source: &'static str,
+    pub factor: u64
+}
+
+/// Every multiplier that's active, they stack by multiplying
+#[derive(Clone, Debug, Default)]
+pub struct ScoreMultipliers(pub Vec<Multiplier>);
+
+impl ScoreMultipliers
+{
+    pub fn total(&self) -> u64
+    {
+        self.0.iter().fold(1, |total, m| total.saturating_mul(m.factor))
+    }
+
+    /// Replace whatever source had before, a factor of 1 or less takes it away
+    pub fn set(&mut self, source: &'static str, factor: u64)
+    {
+        self.0.retain(|m| m.source != source);
+        if factor > 1
+        {
+            self.0.push(Multiplier { source, factor });
+        }
+    }
+}
+
+/// What a claim ended up being worth, published once the points are on the Score
+#[derive(Clone, Copy, Debug)]
+pub struct ScoreEvent
+{
+    pub reason: ScoreReason,
+    pub pos: Vec2,
+    pub base: u64,
+    pub multiplier: u64,
+    pub points: u64
+}
+
+/// Floating "+points" where they were scored
+#[derive(Clone, Debug)]
+pub struct Popup
+{
+    pub text: String,
+    pub pos: Vec2,
+    pub age: f32
+}
+
+impl Popup
+{
+    /// Where it's drawn and how see through it is by now
+    pub fn placement(&self) -> (Vec2, f32)
+    {
+        let t = (self.age / POPUP_TIME).min(1.0);
+        (self.pos - Vec2::new(0.0, POPUP_RISE * t), 1.0 - t)
+    }
+}
+
+/// The HUD's popups, fed off the score events
+#[derive(Clone, Debug, Default)]
+pub struct ScorePopups(pub Vec<Popup>);
+
+impl ScorePopups
+{
+    pub fn add(&mut self, event: &ScoreEvent)
+    {
+        let mut text = format!("+{}", event.points);
+        if event.reason != ScoreReason::Pipe
+        {
+            text.push_str(&format!(" {}", event.reason.to_string().to_uppercase()));
+        }
+        if event.multiplier > 1
+        {
+            text.push_str(&format!(" x{}", event.multiplier));
+        }
+
+        // the ones from this tick haven't aged yet
+        let stacked = self.0.iter().filter(|p| p.age == 0.0).count();
+        let pos = event.pos - Vec2::new(0.0, POPUP_STACK * stacked as f32);
+        self.0.push(Popup { text, pos, age: 0.0 });
+    }
+
+    pub fn update(&mut self, dt: f32)
+    {
+        for popup in &mut self.0
+        {
+            popup.age += dt;
+        }
+        self.0.retain(|p| p.age < POPUP_TIME);
+    }
+}
+
+/// Where this run's points came from, for the console
+#[derive(Clone, Debug, Default)]
+pub struct ScoreStats
+{
+    /// events and points for every reason that's scored so far
+    pub by_reason: Vec<(ScoreReason, u32, u64)>,
+    pub biggest: Option<ScoreEvent>
+}
+
+impl ScoreStats
+{
+    pub fn record(&mut self, event: &ScoreEvent)
+    {
+        match self.by_reason.iter_mut().find(|(reason, _, _)| *reason == event.reason)
+        {
+            Some((_, count, points)) => {
+                *count += 1;
+                *points = points.saturating_add(event.points);
+            },
+            None => self.by_reason.push((event.reason, 1, event.points))
+        }
+        if self.biggest.is_none_or(|b| event.points > b.points)
+        {
+            self.biggest = Some(*event);
+        }
+    }
+}
+
+impl fmt::Display for ScoreStats
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        if self.by_reason.is_empty()
+        {
+            return write!(f, "nothing scored yet");
+        }
+        let reasons: Vec<String> = self.by_reason.iter()
+            .map(|(reason, count, points)| format!("{reason} {count}x {points}pts"))
+            .collect();
+        write!(f, "{}", reasons.join(", "))?;
+        if let Some(b) = self.biggest
+        {
+            write!(f, ", biggest {} ({} {} x{})", b.points, b.reason, b.base, b.multiplier)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn judge()
+    {
+        // a 200 tall gap from 100 to 300 and a 40 tall bird, centre passes are within 15 of 180
+        let gap = Rect { pos_x: 0.0, pos_y: 100.0, size_x: 80.0, size_y: 200.0 };
+        let table = [
+            (99.0, PassStyle::Sloppy),
+            (100.0, PassStyle::NearMiss),
+            (111.9, PassStyle::NearMiss),
+            (112.0, PassStyle::Sloppy),
+            (164.5, PassStyle::Sloppy),
+            (165.5, PassStyle::Centre),
+            (180.0, PassStyle::Centre),
+            (194.5, PassStyle::Centre),
+            (195.5, PassStyle::Sloppy),
+            (248.0, PassStyle::Sloppy),
+            (248.1, PassStyle::NearMiss),
+            (260.0, PassStyle::NearMiss),
+            (261.0, PassStyle::Sloppy)
+        ];
+        for (y, style) in table
+        {
+            let bird = Rect { pos_x: 20.0, pos_y: y, size_x: 40.0, size_y: 40.0 };
+            assert_eq!(PassStyle::judge(&bird, &gap), style, "bird at {y}");
+        }
+    }
+
+    #[test]
+    fn combo()
+    {
+        use PassStyle::*;
+        // the pass, then the count and factor after it
+        let table = [
+            (Centre, 1, 1),
+            (Centre, 2, 1),
+            (NearMiss, 2, 1),
+            (Centre, COMBO_STEP, 2),
+            (Centre, COMBO_STEP + 1, 2),
+            (Sloppy, 0, 1),
+            (Centre, 1, 1)
+        ];
+        let mut combo = Combo::default();
+        for (n, (style, count, factor)) in table.into_iter().enumerate()
+        {
+            combo.pass(style);
+            assert_eq!((combo.count, combo.factor()), (count, factor), "pass {n}");
+        }
+        assert_eq!(combo.best, COMBO_STEP + 1);
+
+        combo.count = COMBO_STEP * 3;
+        assert_eq!(combo.factor(), 4);
+    }
+
+    #[test]
+    fn multipliers()
+    {
+        let mut multipliers = ScoreMultipliers::default();
+        assert_eq!(multipliers.total(), 1);
+
+        multipliers.set("frenzy", 2);
+        multipliers.set("bonus", 3);
+        assert_eq!(multipliers.total(), 6);
+        // setting it again replaces it instead of stacking
+        multipliers.set("frenzy", 4);
+        assert_eq!(multipliers.total(), 12);
+        assert_eq!(multipliers.0.len(), 2);
+
+        multipliers.set("bonus", 1);
+        assert_eq!(multipliers.total(), 4);
+        multipliers.set("frenzy", 0);
+        assert!(multipliers.0.is_empty());
+
+        multipliers.set("huge", u64::MAX);
+        multipliers.set("more", 2);
+        assert_eq!(multipliers.total(), u64::MAX);
+    }
+}
@@ -0,0 +1,968 @@
+use specs::*;
+use specs::shrev::{EventChannel, ReaderId};
+
+use crate::*;
+use crate::autopilot::{Observation, Pilot, PlayerInput, PolicyTable};
+use crate::behaviours::{Breathe, DropIn, Oscillate};
+use crate::collider::{self, Collider, CollisionEvent, CollisionKind, SpriteFrame};
+use crate::debug::{DebugSettings, TimeScale};
+use crate::course::{Course, Physics};
+use crate::obstacles::*;
+use crate::levels::LevelRun;
+use crate::mixer::{AudioManager, SoundEvent};
+use crate::patterns::PatternLibrary;
+use crate::scoring::{Combo, PassStyle, ScoreClaims, ScoreEvent, ScoreMultipliers, ScorePopups, ScoreReason, ScoreStats};
+use crate::triggers::{Trigger, TriggerEvent, TriggerKind, TriggerPhase};
+
+/// Builds the dispatcher that runs every frame while playing.
+/// This is the one place to look at for the order systems run in, specs will
+/// run anything without a dependency between them in parallel
+pub fn build_dispatcher() -> Dispatcher<'static, 'static>
+{
+    DispatcherBuilder::new()
+        .with(SnapshotSystem, "snapshot", &[])
+        .with(PilotSystem, "pilot", &[])
+        .with(GravitySystem, "gravity", &["pilot"])
+        .with(AnimationSystem, "animation", &["pilot"])
+        .with(MovementSystem, "movement", &["gravity", "snapshot"])
+        .with(ObstacleSysten, "obstacles", &["movement"])
+        .with(LevelSystem, "level", &["movement", "obstacles"])
+        .with(ObstacleMotionSystem, "obstacle_motion", &["movement", "obstacles", "level"])
+        .with(ObstacleLayoutSystem, "obstacle_layout", &["obstacle_motion", "snapshot"])
+        .with(CollisionSystem, "collision", &["movement", "obstacle_layout"])
+        .with(GameOverSystem::default(), "game_over", &["collision"])
+        .with(CollisionStatsSystem::default(), "collision_stats", &["collision"])
+        .with(CollisionSoundSystem::default(), "collision_sound", &["collision"])
+        .with(TriggerSystem, "triggers", &["movement", "obstacle_layout"])
+        .with(PipeScoreSystem::default(), "pipe_score", &["triggers", "game_over"])
+        .with(ProgressSystem::default(), "progress", &["triggers", "game_over"])
+        .with(ScoreSystem, "score", &["pipe_score"])
+        .with(ScorePopupSystem::default(), "score_popups", &["score"])
+        .with(ScoreStatsSystem::default(), "score_stats", &["score"])
+        .with(ScoreSoundSystem::default(), "score_sound", &["score"])
+        .with(MusicIntensitySystem, "music_intensity", &["score", "game_over"])
+        .build()
+}
+
+/// Remembers where every Rect starts the tick, for the swept collisions
+struct SnapshotSystem;
+impl<'a> System<'a> for SnapshotSystem
+{
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Rect>,
+        WriteStorage<'a, LastRect>,
+        Write<'a, SystemErrors>,
+    );
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (entities, rect, mut last, mut errors) = data;
+
+        for (ent, r) in (&entities, &rect).join()
+        {
+            if let Err(e) = last.insert(ent, LastRect(*r))
+            {
+                errors.0.push(format!("couldn't snapshot {}: {e}", ent.id()));
+            }
+        }
+    }
+}
+
+/// Flaps whoever's flying the Controllables, the player off the mouse or a bot off what it can see
+struct PilotSystem;
+impl<'a> System<'a> for PilotSystem
+{
+    type SystemData = (
+        Write<'a, PlayerInput>,
+        Write<'a, PolicyTable>,
+        Write<'a, AudioManager>,
+        Read<'a, Gravity>,
+        Read<'a, FlapStrength>,
+        Read<'a, ObstacleSpeed>,
+        Read<'a, ObstaclePool>,
+        ReadStorage<'a, ObstaclePair>,
+        ReadStorage<'a, ObstacleState>,
+        ReadStorage<'a, Rect>,
+        WriteStorage<'a, Velocity>,
+        WriteStorage<'a, Animation>,
+        WriteStorage<'a, Pilot>,
+        ReadStorage<'a, Controllable>,
+        ReadStorage<'a, Dead>,
+    );
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (mut input, mut policy, mut audio, gravity, flap, speed, pool,
+            pairs, states, rect, mut velo, mut animation, mut pilots, control, dead)
+            = data;
+
+        let physics = Physics {
+            gravity: gravity.0,
+            flap: flap.0,
+            speed: speed.0,
+            spacing: pool.spacing
+        };
+        let player_flap = std::mem::take(&mut input.flap);
+
+        for (r, vel, anim, pilot, _, _) in (&rect, &mut velo, (&mut animation).maybe(), &mut pilots, &control, !&dead).join()
+        {
+            let obs = Observation::new(r, vel.y, (&pairs, &states).join(), physics);
+            let flapped = match pilot
+            {
+                Pilot::Player => {
+                    // whatever the player does is what the recorded strategy learns from
+                    policy.record(&obs, player_flap);
+                    player_flap
+                },
+                Pilot::Bot(strategy) => strategy.decide(&obs)
+            };
+
+            if flapped
+            {
+                vel.y = flap_velocity(vel.y, flap.0);
+                if let Some(anim) = anim
+                {
+                    anim.spritesheet.start_animation("flap");
+                }
+                audio.trigger(SoundEvent::Flap);
+            }
+        }
+    }
+}
+
+struct GravitySystem;
+impl<'a> System<'a> for GravitySystem
+{
+    type SystemData = (
+        Read<'a, Delta>,
+        Read<'a, Gravity>,
+        Read<'a, TimeScale>,
+        WriteStorage<'a, Velocity>,
+        ReadStorage<'a, Controllable>,
+        ReadStorage<'a, Dead>,
+    );
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (delta, grav, time, mut velo, control, dead) = data;
+
+        let dt = delta.0.as_secs_f32() * time.scale;
+        for (vel, _, _) in (&mut velo, &control, !&dead).join()
+        {
+            vel.y += grav.0 * dt;
+        }
+    }
+}
+
+struct AnimationSystem;
+impl<'a> System<'a> for AnimationSystem
+{
+    type SystemData = (
+        Read<'a, TimeScale>,
+        WriteStorage<'a, Animation>,
+    );
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (time, mut animation) = data;
+
+        for anim in (&mut animation).join()
+        {
+            anim.spritesheet.tick(time.scale);
+        }
+    }
+}
+
+struct ObstacleSysten;
+impl<'a> System<'a> for ObstacleSysten
+{
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, ObstaclePair>,
+        WriteStorage<'a, ObstacleState>,
+        PairParts<'a>,
+        Read<'a, ObstaclePool>,
+        Write<'a, Course>,
+        Read<'a, PatternLibrary>,
+        Read<'a, Difficulty>,
+        Read<'a, Gravity>,
+        Read<'a, FlapStrength>,
+        Read<'a, ObstacleSpeed>,
+        Read<'a, DebugSettings>,
+        Read<'a, LevelRun>,
+        Write<'a, AudioManager>,
+    );
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (entities, mut pairs, mut states, mut parts, pool, mut course,
+            library, difficulty, gravity, flap, speed, debug, run, mut audio)
+            = data;
+
+        if !debug.run_obstacles
+        {
+            return;
+        }
+
+        // has an obstacle gone outside the screen bounds? back in the pool with it
+        for (entity, pair, state) in (&entities, &mut pairs, &mut states).join()
+        {
+            if *state != ObstacleState::Idle && pair.is_off_screen()
+            {
+                recycle(entity, pair, state, &mut parts);
+            }
+        }
+
+        // levels send their own pipes in, see LevelSystem
+        if run.level.is_some()
+        {
+            return;
+        }
+
+        let last_pos_x = (&pairs, &states).join()
+            .filter(|(_, state)| **state != ObstacleState::Idle)
+            .map(|(pair, _)| pair.pos_x)
+            .fold(0.0, f32::max);
+
+        // is the obstacle in front of us far enough for the next one to join?
+        if SPAWN_X - last_pos_x > course.next_spacing(&library, difficulty.0, pool.spacing)
+        {
+            let physics = Physics {
+                gravity: gravity.0,
+                flap: flap.0,
+                speed: speed.0,
+                spacing: pool.spacing
+            };
+            if let Some((entity, pair, state)) = (&entities, &mut pairs, &mut states).join()
+                .find(|(_, _, state)| **state == ObstacleState::Idle)
+            {
+                let gap = course.next_gap(&library, difficulty.0, pair.width, &physics);
+                place(entity, pair, state, SPAWN_X, &gap, &mut parts);
+                audio.cue();
+            }
+        }
+    }
+}
+
+/// Scrolls through the level being played, sending its pipes in as they come on screen
+struct LevelSystem;
+impl<'a> System<'a> for LevelSystem
+{
+    type SystemData = (
+        Entities<'a>,
+        Write<'a, LevelRun>,
+        WriteStorage<'a, ObstaclePair>,
+        WriteStorage<'a, ObstacleState>,
+        PairParts<'a>,
+        Read<'a, ObstacleSpeed>,
+        Read<'a, TimeScale>,
+        Read<'a, DebugSettings>,
+        Write<'a, AudioManager>,
+    );
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (entities, mut run, mut pairs, mut states, mut parts, speed, time, debug, mut audio)
+            = data;
+
+        if run.level.is_none() || !debug.run_movement
+        {
+            return;
+        }
+        run.advance(speed.0.abs() * time.scale);
+
+        while let Some(pipe) = run.due_pipe()
+        {
+            let Some((entity, pair, state)) = (&entities, &mut pairs, &mut states).join()
+                .find(|(_, _, state)| **state == ObstacleState::Idle)
+            else {
+                // pool's empty, try again next tick
+                break;
+            };
+            place(entity, pair, state, BIRD_X + pipe.x - run.traveled, &pipe.gap, &mut parts);
+            run.pipe_spawned();
+            audio.cue();
+        }
+    }
+}
+
+/// Moves the gaps of pairs that have a behaviour, before their hitboxes get laid out
+struct ObstacleMotionSystem;
+impl<'a> System<'a> for ObstacleMotionSystem
+{
+    type SystemData = (
+        Read<'a, Delta>,
+        Read<'a, TimeScale>,
+        Read<'a, DebugSettings>,
+        WriteStorage<'a, ObstaclePair>,
+        ReadStorage<'a, ObstacleState>,
+        ReadStorage<'a, Velocity>,
+        WriteStorage<'a, Oscillate>,
+        WriteStorage<'a, Breathe>,
+        WriteStorage<'a, DropIn>,
+    );
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (delta, time, debug, mut pairs, states, velocity,
+            mut oscillate, mut breathe, mut drop)
+            = data;
+
+        if !debug.run_movement
+        {
+            return;
+        }
+
+        let dt = delta.0.as_secs_f32() * time.scale;
+        for (pair, state, osc) in (&mut pairs, &states, &mut oscillate).join()
+        {
+            if *state != ObstacleState::Idle
+            {
+                osc.update(pair, dt);
+            }
+        }
+        for (pair, state, breath) in (&mut pairs, &states, &mut breathe).join()
+        {
+            if *state != ObstacleState::Idle
+            {
+                breath.update(pair, dt);
+            }
+        }
+        for (pair, state, velo, drop) in (&mut pairs, &states, &velocity, &mut drop).join()
+        {
+            if *state != ObstacleState::Idle
+            {
+                drop.update(pair, velo.x, time.scale);
+            }
+        }
+    }
+}
+
+/// Lays the top and bottom hitboxes out from their pair
+struct ObstacleLayoutSystem;
+impl<'a> System<'a> for ObstacleLayoutSystem
+{
+    type SystemData = (
+        WriteStorage<'a, Rect>,
+        ReadStorage<'a, ObstacleHitbox>,
+        ReadStorage<'a, ObstacleGate>,
+        ReadStorage<'a, ObstaclePair>,
+    );
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (mut rect, hitboxes, gates, pairs) = data;
+
+        for (r, hitbox) in (&mut rect, &hitboxes).join()
+        {
+            if let Some(pair) = pairs.get(hitbox.pair)
+            {
+                *r = pair.hitbox(hitbox.part);
+            }
+        }
+        for (r, gate) in (&mut rect, &gates).join()
+        {
+            if let Some(pair) = pairs.get(gate.pair)
+            {
+                *r = pair.gap_rect();
+            }
+        }
+    }
+}
+
+/// Checks every trigger against the bird and publishes a TriggerEvent when it goes in or comes back out
+struct TriggerSystem;
+impl<'a> System<'a> for TriggerSystem
+{
+    type SystemData = (
+        Write<'a, EventChannel<TriggerEvent>>,
+        Entities<'a>,
+        ReadStorage<'a, Rect>,
+        WriteStorage<'a, Trigger>,
+        ReadStorage<'a, Controllable>,
+        ReadStorage<'a, Dead>,
+    );
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (mut channel, entities, rect, mut triggers, contr, dead) = data;
+
+        for (ent_t, r_t, trigger) in (&entities, &rect, &mut triggers).join()
+        {
+            // dead birds stay put, whatever they were in they're in for good
+            for (ent, r, _, _) in (&entities, &rect, &contr, !&dead).join()
+            {
+                let overlaps = r.pos_x < r_t.pos_x + r_t.size_x
+                    &&  r.pos_x + r.size_x > r_t.pos_x
+                    &&  r.pos_y < r_t.pos_y + r_t.size_y
+                    &&  r.pos_y + r.size_y > r_t.pos_y;
+                let was_inside = trigger.inside.contains(&ent);
+
+                let phase = match (was_inside, overlaps)
+                {
+                    (false, true) => {
+                        trigger.inside.push(ent);
+                        TriggerPhase::Enter
+                    },
+                    (true, false) => {
+                        trigger.inside.retain(|e| *e != ent);
+                        TriggerPhase::Exit
+                    },
+                    _ => continue
+                };
+                channel.single_write(TriggerEvent { trigger: ent_t, other: ent, kind: trigger.kind, phase });
+            }
+
+            // whoever got deleted while inside is gone without an exit
+            trigger.inside.retain(|e| entities.is_alive(*e));
+        }
+    }
+}
+
+/// Sweeps the bird against everything else that collides and publishes a CollisionEvent per hit,
+/// what a hit means is up to whoever reads the channel
+struct CollisionSystem;
+impl<'a> System<'a> for CollisionSystem
+{
+    type SystemData = (
+        Write<'a, EventChannel<CollisionEvent>>,
+        Entities<'a>,
+        ReadStorage<'a, Rect>,
+        ReadStorage<'a, LastRect>,
+        ReadStorage<'a, Collision>,
+        ReadStorage<'a, Controllable>,
+        ReadStorage<'a, Collider>,
+        ReadStorage<'a, Velocity>,
+        ReadStorage<'a, Animation>,
+        ReadStorage<'a, Boundary>,
+        ReadStorage<'a, ObstacleHitbox>,
+        ReadStorage<'a, Dead>,
+        Read<'a, DebugSettings>,
+    );
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (mut channel, entities, rect, last, collision, contr,
+            colliders, velocity, animation, boundaries, hitboxes, dead, debug)
+            = data;
+
+        if !debug.run_collision
+        {
+            return;
+        }
+
+        let mut events = Vec::new();
+        for (ent_p, r_p, _, _, _, collider, velo, anim)
+            in (&entities, &rect, &collision, &contr, !&dead, colliders.maybe(), velocity.maybe(), animation.maybe()).join()
+        {
+            let vel_y = velo.map_or(0.0, |v| v.y);
+            let hits = |r_p: &Rect, r: &Rect| match collider
+            {
+                // shape first, then the sprite's pixels if there's a mask
+                Some(collider) => {
+                    let pose = collider.pose(r_p, vel_y);
+                    collider.hits(pose, r) && anim.is_none_or(|a| {
+                        let frame = SpriteFrame { src: a.spritesheet.draw(), scale: a.spritesheet.img_scale };
+                        collider.mask_hits(pose, frame, r)
+                    })
+                },
+                // straight forward box collisions
+                // https://developer.mozilla.org/en-US/docs/Games/Techniques/2D_collision_detection
+                None => r_p.pos_x < r.pos_x + r.size_x
+                    &&  r_p.pos_x + r_p.size_x > r.pos_x
+                    &&  r_p.pos_y < r.pos_y + r.size_y
+                    &&  r_p.pos_y + r_p.size_y > r.pos_y
+            };
+
+            // sweep from the start of the tick so nothing gets skipped over
+            let from_p = last.get(ent_p).map_or(r_p, |l| &l.0);
+            // anywhere the bird could have been this tick, so the sweep only runs for what's close
+            let swept_p = match collider
+            {
+                Some(collider) => collider.bounds(from_p).union(&collider.bounds(r_p)),
+                None => from_p.union(r_p)
+            };
+            for (ent, r, _, _) in (&entities, &rect, &collision, !&contr).join()
+            {
+                let from = last.get(ent).map_or(r, |l| &l.0);
+                if !swept_p.overlaps(&from.union(r))
+                {
+                    continue;
+                }
+                let Some(toi) = collider::time_of_impact((from_p, from), (r_p, r), hits) else {
+                    continue;
+                };
+
+                let (at_p, at) = (from_p.lerp(r_p, toi), from.lerp(r, toi));
+                let center = match collider
+                {
+                    Some(collider) => collider.center(collider.pose(&at_p, vel_y)),
+                    None => Vec2::new(at_p.pos_x + at_p.size_x/2.0, at_p.pos_y + at_p.size_y/2.0)
+                };
+                let (point, normal) = collider::contact(center, &at);
+                let kind = match (boundaries.get(ent), hitboxes.get(ent))
+                {
+                    (Some(Boundary::Ceiling), _) => CollisionKind::Ceiling,
+                    (Some(Boundary::Floor), _) => CollisionKind::Floor,
+                    (_, Some(h)) if h.part == ObstaclePart::Top => CollisionKind::ObstacleTop,
+                    (_, Some(_)) => CollisionKind::ObstacleBottom,
+                    _ => CollisionKind::Other
+                };
+                events.push(CollisionEvent { bird: ent_p, other: ent, kind, point, normal, toi });
+            }
+        }
+
+        // earliest first, so readers that only care about the first hit can take that
+        events.sort_by(|a, b| a.toi.total_cmp(&b.toi));
+        channel.iter_write(events);
+    }
+}
+
+/// A hit kills the bird that hit, the game's over once there's none left alive.
+/// God mode keeps everyone alive
+#[derive(Default)]
+struct GameOverSystem
+{
+    reader: Option<ReaderId<CollisionEvent>>
+}
+impl<'a> System<'a> for GameOverSystem
+{
+    type SystemData = (
+        Read<'a, EventChannel<CollisionEvent>>,
+        Write<'a, IsGameover>,
+        Write<'a, AudioManager>,
+        WriteStorage<'a, Rect>,
+        ReadStorage<'a, LastRect>,
+        WriteStorage<'a, Dead>,
+        ReadStorage<'a, Controllable>,
+        Read<'a, DebugSettings>,
+        Write<'a, SystemErrors>,
+    );
+
+    fn setup(&mut self, world: &mut World)
+    {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<EventChannel<CollisionEvent>>().register_reader());
+    }
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (channel, mut is_gameover, mut audio, mut rect, last, mut dead, contr, debug, mut errors) = data;
+
+        let Some(reader) = &mut self.reader else {
+            return;
+        };
+        for event in channel.read(reader)
+        {
+            // events come earliest first, so a bird that hit twice stops at the first one
+            if debug.god_mode || dead.contains(event.bird)
+            {
+                continue;
+            }
+
+            // leave the bird where it hit instead of wherever the tick would've put it
+            if let (Some(r), Some(from)) = (rect.get_mut(event.bird), last.get(event.bird))
+            {
+                *r = from.0.lerp(r, event.toi);
+            }
+            if let Err(e) = dead.insert(event.bird, Dead)
+            {
+                errors.0.push(format!("couldn't kill {}: {e}", event.bird.id()));
+            }
+        }
+
+        let anyone_alive = (&contr, !&dead).join().next().is_some();
+        if !is_gameover.0 && !anyone_alive && (&contr).join().next().is_some()
+        {
+            is_gameover.0 = true;
+            audio.trigger(SoundEvent::GameOver);
+        }
+    }
+}
+
+/// Thud for every hit, the mixer's cooldown keeps god mode from turning it into a buzz
+#[derive(Default)]
+struct CollisionSoundSystem
+{
+    reader: Option<ReaderId<CollisionEvent>>
+}
+impl<'a> System<'a> for CollisionSoundSystem
+{
+    type SystemData = (
+        Read<'a, EventChannel<CollisionEvent>>,
+        Write<'a, AudioManager>,
+    );
+
+    fn setup(&mut self, world: &mut World)
+    {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<EventChannel<CollisionEvent>>().register_reader());
+    }
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (channel, mut audio) = data;
+
+        let Some(reader) = &mut self.reader else {
+            return;
+        };
+        if channel.read(reader).count() > 0
+        {
+            audio.trigger(SoundEvent::Hit);
+        }
+    }
+}
+
+/// Counts hits for the console, another reader of the collision events
+#[derive(Default)]
+struct CollisionStatsSystem
+{
+    reader: Option<ReaderId<CollisionEvent>>
+}
+impl<'a> System<'a> for CollisionStatsSystem
+{
+    type SystemData = (
+        Read<'a, EventChannel<CollisionEvent>>,
+        Write<'a, CollisionStats>,
+    );
+
+    fn setup(&mut self, world: &mut World)
+    {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<EventChannel<CollisionEvent>>().register_reader());
+    }
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (channel, mut stats) = data;
+
+        let Some(reader) = &mut self.reader else {
+            return;
+        };
+        for event in channel.read(reader)
+        {
+            stats.hits += 1;
+            stats.last = Some(*event);
+        }
+    }
+}
+
+struct MovementSystem;
+impl<'a> System<'a> for MovementSystem
+{
+    type SystemData = (
+        WriteStorage<'a, Rect>,
+        WriteStorage<'a, ObstaclePair>,
+        ReadStorage<'a, ObstacleState>,
+        ReadStorage<'a, Velocity>,
+        ReadStorage<'a, Dead>,
+        Read<'a, TimeScale>,
+        Read<'a, DebugSettings>,
+    );
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (mut rect, mut pairs, states, velocity, dead, time, debug)
+            = data;
+
+        if !debug.run_movement
+        {
+            return;
+        }
+
+        for (velo, r, _) in (&velocity, &mut rect, !&dead).join()
+        {
+            r.pos_y += velo.y * time.scale;
+            r.pos_x += velo.x * time.scale;
+        }
+
+        // idle pairs sit in the pool until they're sent in
+        for (velo, pair, state) in (&velocity, &mut pairs, &states).join()
+        {
+            if *state != ObstacleState::Idle
+            {
+                pair.pos_x += velo.x * time.scale;
+            }
+        }
+    }
+}
+
+/// A pair is worth a point once, the moment the bird gets into its gap while it's still Active,
+/// how cleanly it went in is judged right then for the bonuses and the combo
+#[derive(Default)]
+struct PipeScoreSystem
+{
+    reader: Option<ReaderId<TriggerEvent>>
+}
+impl<'a> System<'a> for PipeScoreSystem
+{
+    type SystemData = (
+        Read<'a, EventChannel<TriggerEvent>>,
+        Write<'a, ScoreClaims>,
+        Write<'a, Combo>,
+        Write<'a, ScoreMultipliers>,
+        ReadStorage<'a, Rect>,
+        ReadStorage<'a, ObstacleGate>,
+        WriteStorage<'a, ObstacleState>,
+        ReadStorage<'a, Controllable>,
+        ReadStorage<'a, Dead>,
+    );
+
+    fn setup(&mut self, world: &mut World)
+    {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<EventChannel<TriggerEvent>>().register_reader());
+    }
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (channel, mut claims, mut combo, mut multipliers, rect, gates, mut states, contr, dead) = data;
+
+        let Some(reader) = &mut self.reader else {
+            return;
+        };
+        for event in channel.read(reader)
+        {
+            // only the bird scores, whatever else might set a trigger off
+            if event.kind != TriggerKind::Score || event.phase != TriggerPhase::Enter || !contr.contains(event.other)
+            {
+                continue;
+            }
+            // crashing on the way in isn't a pass, and it leaves the pair for a bird that's still flying
+            if dead.contains(event.other)
+            {
+                continue;
+            }
+
+            // idle pairs can't score, that also covers exits left over from a reset
+            let state = gates.get(event.trigger).and_then(|g| states.get_mut(g.pair));
+            if let Some(state) = state.filter(|s| **s == ObstacleState::Active)
+            {
+                *state = ObstacleState::Passed;
+                let Some(bird) = rect.get(event.other) else {
+                    continue;
+                };
+                let pos = Vec2::new(bird.pos_x + bird.size_x/2.0, bird.pos_y);
+                claims.push(ScoreReason::Pipe, pos);
+
+                // the gate's Rect is the gap
+                let Some(gap) = rect.get(event.trigger) else {
+                    continue;
+                };
+                let style = PassStyle::judge(bird, gap);
+                match style
+                {
+                    PassStyle::NearMiss => claims.push(ScoreReason::NearMiss, pos),
+                    PassStyle::Centre => claims.push(ScoreReason::CentrePass, pos),
+                    PassStyle::Sloppy => {}
+                }
+                combo.pass(style);
+                multipliers.set("combo", combo.factor());
+            }
+        }
+    }
+}
+
+/// Counts every bird's frames alive and gaps gone into, for whatever wants to rank them
+#[derive(Default)]
+struct ProgressSystem
+{
+    reader: Option<ReaderId<TriggerEvent>>
+}
+impl<'a> System<'a> for ProgressSystem
+{
+    type SystemData = (
+        Read<'a, EventChannel<TriggerEvent>>,
+        WriteStorage<'a, Progress>,
+        ReadStorage<'a, Dead>,
+    );
+
+    fn setup(&mut self, world: &mut World)
+    {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<EventChannel<TriggerEvent>>().register_reader());
+    }
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (channel, mut progress, dead) = data;
+
+        for (prog, _) in (&mut progress, !&dead).join()
+        {
+            prog.frames += 1;
+        }
+
+        let Some(reader) = &mut self.reader else {
+            return;
+        };
+        for event in channel.read(reader)
+        {
+            if event.kind != TriggerKind::Score || event.phase != TriggerPhase::Enter
+            {
+                continue;
+            }
+            if let Some(prog) = progress.get_mut(event.other)
+            {
+                prog.gaps += 1;
+            }
+        }
+    }
+}
+
+/// Prices this tick's claims, adds them to the Score and publishes a ScoreEvent for each
+struct ScoreSystem;
+impl<'a> System<'a> for ScoreSystem
+{
+    type SystemData = (
+        Write<'a, EventChannel<ScoreEvent>>,
+        Write<'a, ScoreClaims>,
+        Read<'a, ScoreMultipliers>,
+        Write<'a, Score>,
+        Write<'a, Difficulty>,
+    );
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (mut channel, mut claims, multipliers, mut score, mut difficulty) = data;
+
+        let multiplier = multipliers.total();
+        let before = score.0;
+        for claim in claims.0.drain(..)
+        {
+            let base = claim.reason.base_points();
+            let points = base.saturating_mul(multiplier);
+            score.0 = score.0.saturating_add(points);
+            channel.single_write(ScoreEvent { reason: claim.reason, pos: claim.pos, base, multiplier, points });
+        }
+
+        // only when the score moves, so a difficulty set from the console sticks until then
+        if score.0 != before
+        {
+            difficulty.0 = u8::try_from(score.0 / DIFFICULTY_STEP).unwrap_or(u8::MAX);
+        }
+    }
+}
+
+/// Turns score events into the HUD's popups and floats them away
+#[derive(Default)]
+struct ScorePopupSystem
+{
+    reader: Option<ReaderId<ScoreEvent>>
+}
+impl<'a> System<'a> for ScorePopupSystem
+{
+    type SystemData = (
+        Read<'a, EventChannel<ScoreEvent>>,
+        Write<'a, ScorePopups>,
+        Read<'a, Delta>,
+        Read<'a, TimeScale>,
+    );
+
+    fn setup(&mut self, world: &mut World)
+    {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<EventChannel<ScoreEvent>>().register_reader());
+    }
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (channel, mut popups, delta, time) = data;
+
+        popups.update(delta.0.as_secs_f32() * time.scale);
+        if let Some(reader) = &mut self.reader
+        {
+            for event in channel.read(reader)
+            {
+                popups.add(event);
+            }
+        }
+    }
+}
+
+/// Brings the music's stems in as the score goes up, and drops them all on game over
+struct MusicIntensitySystem;
+impl<'a> System<'a> for MusicIntensitySystem
+{
+    type SystemData = (
+        Read<'a, Score>,
+        Read<'a, IsGameover>,
+        Write<'a, AudioManager>,
+    );
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (score, is_gameover, mut audio) = data;
+
+        // same scale as Difficulty but without the steps, so stems can sit in between levels
+        let intensity = if is_gameover.0 { 0.0 } else { score.0 as f32 / DIFFICULTY_STEP as f32 };
+        audio.set_intensity(intensity);
+    }
+}
+
+/// Score sound for every score event
+#[derive(Default)]
+struct ScoreSoundSystem
+{
+    reader: Option<ReaderId<ScoreEvent>>
+}
+impl<'a> System<'a> for ScoreSoundSystem
+{
+    type SystemData = (
+        Read<'a, EventChannel<ScoreEvent>>,
+        Write<'a, AudioManager>,
+    );
+
+    fn setup(&mut self, world: &mut World)
+    {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<EventChannel<ScoreEvent>>().register_reader());
+    }
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (channel, mut audio) = data;
+
+        let Some(reader) = &mut self.reader else {
+            return;
+        };
+        // bonuses come in the same tick as their pipe, one sound for all of it
+        if channel.read(reader).count() > 0
+        {
+            audio.trigger(SoundEvent::Score);
+        }
+    }
+}
+
+/// Keeps the per reason breakdown of the run's points
+#[derive(Default)]
+struct ScoreStatsSystem
+{
+    reader: Option<ReaderId<ScoreEvent>>
+}
+impl<'a> System<'a> for ScoreStatsSystem
+{
+    type SystemData = (
+        Read<'a, EventChannel<ScoreEvent>>,
+        Write<'a, ScoreStats>,
+    );
+
+    fn setup(&mut self, world: &mut World)
+    {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<EventChannel<ScoreEvent>>().register_reader());
+    }
+
+    fn run(&mut self, data: Self::SystemData)
+    {
+        let (channel, mut stats) = data;
+
+        let Some(reader) = &mut self.reader else {
+            return;
+        };
+        for event in channel.read(reader)
+        {
+            stats.record(event);
+        }
+    }
+}
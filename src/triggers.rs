@@ -0,0 +1,44 @@
+use specs::*;
+
+/// What a trigger is for, so readers of the events can pick out the ones they care about
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerKind
+{
+    /// the gap of an obstacle pair, the bird gets a point going in
+    Score
+}
+
+/// A sensor, its Rect gets checked against the bird every tick but nothing ever bumps into it
+#[derive(Component, Debug)]
+#[storage(DenseVecStorage)]
+pub struct Trigger
+{
+    pub kind: TriggerKind,
+    /// whoever's overlapping it right now, so enter and exit only fire once each
+    pub inside: Vec<Entity>
+}
+
+impl Trigger
+{
+    pub fn new(kind: TriggerKind) -> Trigger
+    {
+        Trigger { kind, inside: Vec::new() }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerPhase
+{
+    Enter,
+    Exit
+}
+
+/// Published on an EventChannel whenever something goes in or out of a trigger
+#[derive(Clone, Copy, Debug)]
+pub struct TriggerEvent
+{
+    pub trigger: Entity,
+    pub other: Entity,
+    pub kind: TriggerKind,
+    pub phase: TriggerPhase
+}
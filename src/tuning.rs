@@ -0,0 +1,90 @@
+use ggez::Context;
+use specs::*;
+
+use crate::*;
+use crate::obstacles::ObstaclePair;
+
+/// Tuning values live in here as `name = value` lines, `#` starts a comment
+pub const TUNING_FILE:&str = "/tuning.cfg";
+
+pub const TUNING_VARS:[&str; 3] = ["gravity", "flap", "speed"];
+
+pub fn get(world: &World, var: &str) -> Option<f32>
+{
+    match var
+    {
+        "gravity" => Some(world.read_resource::<Gravity>().0),
+        "flap" => Some(world.read_resource::<FlapStrength>().0),
+        "speed" => Some(world.read_resource::<ObstacleSpeed>().0),
+        _ => None
+    }
+}
+
+pub fn set(world: &mut World, var: &str, value: f32) -> Result<(), String>
+{
+    // nan or inf would end up in every position it touches
+    if !value.is_finite()
+    {
+        return Err(format!("{var} can't be {value}"));
+    }
+    match var
+    {
+        "gravity" => world.write_resource::<Gravity>().0 = value,
+        "flap" => world.write_resource::<FlapStrength>().0 = value,
+        "speed" => {
+            if value > -course::MIN_SPEED
+            {
+                return Err(format!("speed has to be {} or less, obstacles go left", -course::MIN_SPEED));
+            }
+            world.write_resource::<ObstacleSpeed>().0 = value;
+
+            // obstacles already on screen should change speed right away too
+            let pairs = world.read_storage::<ObstaclePair>();
+            let mut velo = world.write_storage::<Velocity>();
+            for (vel, _) in (&mut velo, &pairs).join()
+            {
+                vel.x = value;
+            }
+        },
+        _ => return Err(format!("unknown tuning value '{var}'"))
+    }
+    Ok(())
+}
+
+/// Apply every line of a tuning config, bad lines are skipped and reported back
+pub fn apply(world: &mut World, contents: &str) -> Vec<String>
+{
+    let mut errors = Vec::new();
+    for (n, line) in contents.lines().enumerate()
+    {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty()
+        {
+            continue;
+        }
+
+        let Some((var, value)) = line.split_once('=') else {
+            errors.push(format!("{TUNING_FILE}:{} expected name = value", n + 1));
+            continue;
+        };
+        let result = value.trim().parse::<f32>()
+            .map_err(|e| format!("'{}' isn't a number ({e})", value.trim()))
+            .and_then(|value| set(world, var.trim(), value));
+        if let Err(err) = result
+        {
+            errors.push(format!("{TUNING_FILE}:{} {err}", n + 1));
+        }
+    }
+    errors
+}
+
+/// Load the tuning config through the ggez filesystem, a missing file just keeps the defaults
+pub fn load(ctx: &mut Context, world: &mut World) -> Vec<String>
+{
+    match read_asset(ctx, TUNING_FILE)
+    {
+        Ok(Some(contents)) => apply(world, &contents),
+        Ok(None) => Vec::new(),
+        Err(err) => vec![err]
+    }
+}